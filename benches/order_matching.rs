@@ -1,10 +1,11 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use rust_decimal_macros::dec;
-use rust_hft_trading_engine::{MatchingEngine, Order, OrderSide, OrderType};
+use rust_hft_trading_engine::{Instrument, MatchingEngine, Order, OrderSide, OrderType};
 
 fn benchmark_order_submission(c: &mut Criterion) {
     c.bench_function("submit_limit_order", |b| {
         let engine = MatchingEngine::new();
+        engine.register_instrument(Instrument::new("AAPL", dec!(0.01), "USD"));
         b.iter(|| {
             let order = Order::new(
                 "AAPL".to_string(),
@@ -24,7 +25,8 @@ fn benchmark_order_matching(c: &mut Criterion) {
     c.bench_function("match_orders", |b| {
         b.iter(|| {
             let engine = MatchingEngine::new();
-            
+            engine.register_instrument(Instrument::new("AAPL", dec!(0.01), "USD"));
+
             let sell_order = Order::new(
                 "AAPL".to_string(),
                 OrderSide::Sell,
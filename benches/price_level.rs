@@ -0,0 +1,154 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use rust_hft_trading_engine::{Order, OrderBook, OrderSide, OrderType, Qty, Rounding};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// The previous layout: each level owns a `Vec` of entries keyed and sized
+/// in `Decimal`, and cancels search it linearly.
+mod vec_layout {
+    use super::*;
+
+    #[derive(Clone)]
+    pub struct PriceLevel {
+        pub total_quantity: Decimal,
+        pub orders: Vec<(Uuid, Decimal)>,
+    }
+
+    #[derive(Clone, Default)]
+    pub struct OrderBook {
+        pub bids: BTreeMap<Decimal, PriceLevel>,
+    }
+
+    impl OrderBook {
+        pub fn add_order(&mut self, order: &Order) {
+            let price = order.price.unwrap_or(Decimal::ZERO);
+            let level = self.bids.entry(price).or_insert_with(|| PriceLevel {
+                total_quantity: Decimal::ZERO,
+                orders: Vec::new(),
+            });
+            level.orders.push((order.id, order.quantity));
+            level.total_quantity += order.quantity;
+        }
+
+        pub fn remove_order(&mut self, order: &Order) {
+            let price = order.price.unwrap_or(Decimal::ZERO);
+            if let Some(level) = self.bids.get_mut(&price) {
+                if let Some(position) = level.orders.iter().position(|(id, _)| *id == order.id) {
+                    let (_, quantity) = level.orders.remove(position);
+                    level.total_quantity -= quantity;
+                }
+                if level.orders.is_empty() {
+                    self.bids.remove(&price);
+                }
+            }
+        }
+
+        /// The matching loop cloned the level's entries before walking them.
+        pub fn walk(&self, price: Decimal) -> Decimal {
+            let orders = self.bids[&price].orders.clone();
+            orders.iter().map(|(_, quantity)| *quantity).sum()
+        }
+    }
+}
+
+fn orders(count: usize) -> Vec<Order> {
+    (0..count)
+        .map(|i| {
+            Order::new(
+                "AAPL".to_string(),
+                OrderSide::Buy,
+                OrderType::Limit,
+                dec!(100),
+                Some(dec!(150.00) - Decimal::new(i as i64 % 4, 2)),
+                None,
+                format!("user{}", i),
+            )
+        })
+        .collect()
+}
+
+fn benchmark_cancel(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cancel_and_replace");
+
+    for count in [100, 1_000, 10_000] {
+        let orders = orders(count);
+        let target = &orders[count / 2];
+
+        let mut vec_book = vec_layout::OrderBook::default();
+        let mut arena_book = OrderBook::new("AAPL".to_string());
+        for order in &orders {
+            vec_book.add_order(order);
+            arena_book.add_order(order).unwrap();
+        }
+
+        group.bench_with_input(BenchmarkId::new("vec", count), &count, |b, _| {
+            b.iter(|| {
+                vec_book.remove_order(black_box(target));
+                vec_book.add_order(target);
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("arena", count), &count, |b, _| {
+            b.iter(|| {
+                arena_book.remove_order(black_box(target));
+                arena_book.add_order(target).unwrap();
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn benchmark_walk(c: &mut Criterion) {
+    let mut group = c.benchmark_group("walk_level");
+
+    for count in [100, 1_000, 10_000] {
+        let orders = orders(count);
+        let price = dec!(150.00);
+
+        let mut vec_book = vec_layout::OrderBook::default();
+        let mut arena_book = OrderBook::new("AAPL".to_string());
+        for order in &orders {
+            vec_book.add_order(order);
+            arena_book.add_order(order).unwrap();
+        }
+
+        group.bench_with_input(BenchmarkId::new("vec", count), &count, |b, _| {
+            b.iter(|| black_box(vec_book.walk(price)))
+        });
+
+        group.bench_with_input(BenchmarkId::new("arena", count), &count, |b, _| {
+            b.iter(|| {
+                let level = arena_book.level(OrderSide::Buy, price).unwrap();
+                black_box(arena_book.queue(level).map(|entry| entry.quantity).sum::<Qty>())
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn benchmark_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("level_lookup");
+    let orders = orders(1_000);
+
+    let mut vec_book = vec_layout::OrderBook::default();
+    let mut arena_book = OrderBook::new("AAPL".to_string());
+    for order in &orders {
+        vec_book.add_order(order);
+        arena_book.add_order(order).unwrap();
+    }
+
+    let price = dec!(149.98);
+    let fixed_price = arena_book.to_price(price, Rounding::Exact).unwrap();
+
+    group.bench_function("decimal", |b| b.iter(|| black_box(vec_book.bids.contains_key(black_box(&price)))));
+    group.bench_function("fixed", |b| b.iter(|| black_box(arena_book.bids.contains_key(black_box(&fixed_price)))));
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_cancel, benchmark_walk, benchmark_lookup);
+criterion_main!(benches);
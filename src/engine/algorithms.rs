@@ -0,0 +1,276 @@
+use rust_decimal::{Decimal, RoundingStrategy};
+use uuid::Uuid;
+
+use crate::models::Qty;
+
+/// A resting order competing for a share of an incoming order at one price
+/// level, in time priority.
+#[derive(Debug, Clone, Copy)]
+pub struct RestingOrder<'a> {
+    pub order_id: Uuid,
+    pub user_id: &'a str,
+    /// Quantity currently available to trade, i.e. the displayed slice
+    pub quantity: Qty,
+}
+
+/// Decides how an incoming quantity is split across the orders resting at a
+/// single price level. Quantities are whole lots of the book.
+///
+/// Implementations return one allocation per resting order, aligned with
+/// `resting`. Each allocation must not exceed that order's quantity, and
+/// together they must add up to `quantity` or to everything available,
+/// whichever is smaller. Fills are executed in queue order.
+pub trait MatchingAlgorithm: Send + Sync {
+    fn allocate(&self, quantity: Qty, resting: &[RestingOrder]) -> Vec<Qty>;
+}
+
+/// First in, first out within a level.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PriceTime;
+
+impl MatchingAlgorithm for PriceTime {
+    fn allocate(&self, quantity: Qty, resting: &[RestingOrder]) -> Vec<Qty> {
+        let mut left = quantity;
+        resting
+            .iter()
+            .map(|order| {
+                let allocated = left.min(order.quantity);
+                left -= allocated;
+                allocated
+            })
+            .collect()
+    }
+}
+
+/// Splits in proportion to resting size, rounded down to whole lots. Lots
+/// lost to rounding go one at a time to orders in time priority.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProRata;
+
+impl MatchingAlgorithm for ProRata {
+    fn allocate(&self, quantity: Qty, resting: &[RestingOrder]) -> Vec<Qty> {
+        let sizes: Vec<Qty> = resting.iter().map(|order| order.quantity).collect();
+        pro_rata(quantity, &sizes)
+    }
+}
+
+/// Fills the order at the front of the queue first and splits whatever is
+/// left pro-rata across the rest of the level.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TopOrderProRata;
+
+impl MatchingAlgorithm for TopOrderProRata {
+    fn allocate(&self, quantity: Qty, resting: &[RestingOrder]) -> Vec<Qty> {
+        let Some((top, rest)) = resting.split_first() else {
+            return Vec::new();
+        };
+
+        let top_allocation = quantity.min(top.quantity);
+        let sizes: Vec<Qty> = rest.iter().map(|order| order.quantity).collect();
+
+        let mut allocations = vec![top_allocation];
+        allocations.extend(pro_rata(quantity - top_allocation, &sizes));
+        allocations
+    }
+}
+
+/// Guarantees designated lead market makers a percentage of each incoming
+/// order, rounded down to whole lots and shared between their orders in time
+/// priority. The remainder is allocated across the whole level by `rest`.
+pub struct LmmSplit {
+    pub market_makers: Vec<String>,
+    /// Share of the incoming quantity reserved for market makers, in percent
+    percentage: Decimal,
+    pub rest: Box<dyn MatchingAlgorithm>,
+}
+
+impl LmmSplit {
+    /// Decimal places a market maker percentage is kept to, which keeps the
+    /// share of any quantity within `i128` arithmetic.
+    pub const PERCENTAGE_SCALE: u32 = 6;
+
+    /// Fails unless `percentage` is between 0 and 100. It is rounded down to
+    /// `PERCENTAGE_SCALE` decimal places.
+    pub fn new(
+        market_makers: Vec<String>,
+        percentage: Decimal,
+        rest: impl MatchingAlgorithm + 'static,
+    ) -> Result<Self, String> {
+        if percentage < Decimal::ZERO || percentage > Decimal::ONE_HUNDRED {
+            return Err(format!("Market maker percentage {} must be between 0 and 100", percentage));
+        }
+
+        Ok(Self {
+            market_makers,
+            percentage: percentage.round_dp_with_strategy(Self::PERCENTAGE_SCALE, RoundingStrategy::ToZero),
+            rest: Box::new(rest),
+        })
+    }
+
+    pub fn percentage(&self) -> Decimal {
+        self.percentage
+    }
+}
+
+impl MatchingAlgorithm for LmmSplit {
+    fn allocate(&self, quantity: Qty, resting: &[RestingOrder]) -> Vec<Qty> {
+        let percentage = self.percentage.normalize();
+        let mut entitlement = quantity.mul_div_floor(percentage.mantissa(), 100 * 10i128.pow(percentage.scale()));
+
+        let mut allocations: Vec<Qty> = resting
+            .iter()
+            .map(|order| {
+                if !self.market_makers.iter().any(|user| user == order.user_id) {
+                    return Qty::ZERO;
+                }
+                let allocated = entitlement.min(order.quantity);
+                entitlement -= allocated;
+                allocated
+            })
+            .collect();
+
+        let allocated: Qty = allocations.iter().sum();
+        let remaining: Vec<RestingOrder> = resting
+            .iter()
+            .zip(&allocations)
+            .map(|(order, allocated)| RestingOrder {
+                quantity: order.quantity - *allocated,
+                ..*order
+            })
+            .collect();
+
+        let rest = self.rest.allocate(quantity - allocated, &remaining);
+        for (allocation, extra) in allocations.iter_mut().zip(rest) {
+            *allocation += extra;
+        }
+        allocations
+    }
+}
+
+/// Proportional split of `quantity` over `sizes`. Each share is rounded down
+/// to a whole lot, and the leftover is handed out one lot per order per pass
+/// in time priority, so the result never depends on anything but queue
+/// order.
+fn pro_rata(quantity: Qty, sizes: &[Qty]) -> Vec<Qty> {
+    let total: Qty = sizes.iter().sum();
+    if quantity >= total {
+        return sizes.to_vec();
+    }
+    if quantity <= Qty::ZERO {
+        return vec![Qty::ZERO; sizes.len()];
+    }
+
+    let mut allocations: Vec<Qty> = sizes
+        .iter()
+        .map(|&size| quantity.mul_div_floor(size.units().into(), total.units().into()).min(size))
+        .collect();
+
+    let mut leftover = quantity - allocations.iter().sum();
+    while leftover > Qty::ZERO {
+        for (allocation, &size) in allocations.iter_mut().zip(sizes) {
+            let extra = Qty::new(1).min(size - *allocation);
+            *allocation += extra;
+            leftover -= extra;
+            if leftover <= Qty::ZERO {
+                break;
+            }
+        }
+    }
+
+    allocations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn level<'a>(orders: &[(&'a str, i64)]) -> Vec<RestingOrder<'a>> {
+        orders
+            .iter()
+            .map(|&(user_id, lots)| RestingOrder {
+                order_id: Uuid::new_v4(),
+                user_id,
+                quantity: Qty::new(lots),
+            })
+            .collect()
+    }
+
+    fn lots(lots: &[i64]) -> Vec<Qty> {
+        lots.iter().map(|&lots| Qty::new(lots)).collect()
+    }
+
+    #[test]
+    fn test_price_time_allocation() {
+        let resting = level(&[("a", 30), ("b", 50), ("c", 20)]);
+
+        assert_eq!(PriceTime.allocate(Qty::new(60), &resting), lots(&[30, 30, 0]));
+        assert_eq!(PriceTime.allocate(Qty::new(500), &resting), lots(&[30, 50, 20]));
+    }
+
+    #[test]
+    fn test_pro_rata_allocation() {
+        let resting = level(&[("a", 10), ("b", 30), ("c", 60)]);
+
+        // Exact shares need no rounding
+        assert_eq!(ProRata.allocate(Qty::new(50), &resting), lots(&[5, 15, 30]));
+
+        // 7 splits as 0.7 / 2.1 / 4.2 -> 0 / 2 / 4, and the leftover lot
+        // goes to the oldest order
+        assert_eq!(ProRata.allocate(Qty::new(7), &resting), lots(&[1, 2, 4]));
+
+        // Two leftover lots go to the first two orders in time priority
+        let resting = level(&[("a", 4), ("b", 4), ("c", 4)]);
+        assert_eq!(ProRata.allocate(Qty::new(8), &resting), lots(&[3, 3, 2]));
+    }
+
+    #[test]
+    fn test_top_order_pro_rata_allocation() {
+        let resting = level(&[("a", 20), ("b", 40), ("c", 120)]);
+
+        // The top order fills completely, the remaining 41 splits 10.25 / 30.75
+        assert_eq!(TopOrderProRata.allocate(Qty::new(61), &resting), lots(&[20, 11, 30]));
+        assert_eq!(TopOrderProRata.allocate(Qty::new(15), &resting), lots(&[15, 0, 0]));
+    }
+
+    #[test]
+    fn test_lmm_split_allocation() {
+        let resting = level(&[("a", 50), ("lmm", 50), ("b", 100)]);
+        let algorithm = LmmSplit::new(vec!["lmm".to_string()], dec!(40), ProRata).unwrap();
+
+        // 40% of 45 rounds down to 18 for the market maker; the other 27 is
+        // split 50 / 32 / 100 -> 7.41 / 4.74 / 14.81 -> 7 / 4 / 14, and the
+        // two leftover lots go to the first two orders
+        assert_eq!(algorithm.allocate(Qty::new(45), &resting), lots(&[8, 23, 14]));
+
+        // The guarantee is capped by the market maker's size
+        let algorithm = LmmSplit::new(vec!["lmm".to_string()], dec!(50), PriceTime).unwrap();
+        assert_eq!(algorithm.allocate(Qty::new(120), &resting), lots(&[50, 50, 20]));
+
+        // Fractional percentages round down too: 12.5% of 45 is 5.625
+        let algorithm = LmmSplit::new(vec!["lmm".to_string()], dec!(12.50), PriceTime).unwrap();
+        assert_eq!(algorithm.allocate(Qty::new(45), &resting), lots(&[40, 5, 0]));
+    }
+
+    #[test]
+    fn test_lmm_split_percentage_bounds() {
+        let makers = || vec!["lmm".to_string()];
+        assert!(LmmSplit::new(makers(), dec!(-1), PriceTime).is_err());
+        assert!(LmmSplit::new(makers(), dec!(100.01), PriceTime).is_err());
+        assert!(LmmSplit::new(makers(), dec!(0), PriceTime).is_ok());
+
+        // A market maker can be guaranteed everything
+        let resting = level(&[("a", 50), ("lmm", 100)]);
+        let algorithm = LmmSplit::new(makers(), dec!(100), PriceTime).unwrap();
+        assert_eq!(algorithm.allocate(Qty::new(60), &resting), lots(&[0, 60]));
+
+        // Long fractions are cut to a fixed scale, so large quantities cannot
+        // overflow the share calculation
+        let algorithm = LmmSplit::new(makers(), dec!(33.3333333333333333333333), PriceTime).unwrap();
+        assert_eq!(algorithm.percentage(), dec!(33.333333));
+        // 99 guaranteed lots, then price-time over the remaining 201
+        assert_eq!(algorithm.allocate(Qty::new(300), &resting), lots(&[50, 100]));
+        let resting = level(&[("lmm", i64::MAX)]);
+        assert_eq!(algorithm.allocate(Qty::new(i64::MAX), &resting), lots(&[i64::MAX]));
+    }
+}
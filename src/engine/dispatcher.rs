@@ -0,0 +1,378 @@
+use crossbeam::channel::{bounded, unbounded, Sender};
+use dashmap::DashMap;
+use rust_decimal::Decimal;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use uuid::Uuid;
+
+use super::MatchingEngine;
+use crate::models::{Order, Trade};
+
+/// A state change for one symbol, applied on that symbol's dispatch lane.
+#[derive(Debug, Clone)]
+pub enum Command {
+    Submit(Box<Order>),
+    Cancel(Uuid),
+    Amend {
+        order_id: Uuid,
+        quantity: Option<Decimal>,
+        price: Option<Decimal>,
+    },
+    Replace {
+        order_id: Uuid,
+        replacement: Box<Order>,
+    },
+}
+
+/// What a command produced, in the shape of the matching engine call.
+#[derive(Debug, Clone)]
+pub enum CommandResult {
+    Submitted(Result<Vec<Trade>, String>),
+    Cancelled(Result<(), String>),
+    Amended(Result<Vec<Trade>, String>),
+    Replaced(Result<Vec<Trade>, String>),
+}
+
+/// The order entry calls of the matching engine, routed as `Command`s by
+/// something that applies each symbol's commands one at a time in arrival
+/// order.
+pub trait OrderEntry {
+    /// Applies `command` for `symbol` and waits for the result.
+    fn execute(&self, symbol: &str, command: Command) -> Result<CommandResult, String>;
+
+    /// The symbol of an order the engine knows about.
+    fn symbol_of(&self, order_id: Uuid) -> Result<String, String>;
+
+    fn submit_order(&self, order: Order) -> Result<Vec<Trade>, String> {
+        let symbol = order.symbol.clone();
+        match self.execute(&symbol, Command::Submit(Box::new(order)))? {
+            CommandResult::Submitted(result) => result,
+            other => Err(format!("Unexpected result {:?}", other)),
+        }
+    }
+
+    fn cancel_order(&self, order_id: Uuid) -> Result<(), String> {
+        let symbol = self.symbol_of(order_id)?;
+        match self.execute(&symbol, Command::Cancel(order_id))? {
+            CommandResult::Cancelled(result) => result,
+            other => Err(format!("Unexpected result {:?}", other)),
+        }
+    }
+
+    fn amend_order(
+        &self,
+        order_id: Uuid,
+        quantity: Option<Decimal>,
+        price: Option<Decimal>,
+    ) -> Result<Vec<Trade>, String> {
+        let symbol = self.symbol_of(order_id)?;
+        match self.execute(&symbol, Command::Amend { order_id, quantity, price })? {
+            CommandResult::Amended(result) => result,
+            other => Err(format!("Unexpected result {:?}", other)),
+        }
+    }
+
+    fn replace_order(&self, order_id: Uuid, replacement: Order) -> Result<Vec<Trade>, String> {
+        let symbol = self.symbol_of(order_id)?;
+        let replacement = Box::new(replacement);
+        match self.execute(&symbol, Command::Replace { order_id, replacement })? {
+            CommandResult::Replaced(result) => result,
+            other => Err(format!("Unexpected result {:?}", other)),
+        }
+    }
+}
+
+type Envelope = (Command, Sender<Result<CommandResult, String>>);
+
+/// Runs the order commands for each symbol on a lane of its own, one at a
+/// time in arrival order, so commands for the same symbol never contend
+/// for its book locks and cannot interleave.
+///
+/// The lanes only order commands; they do not own any state. The engine
+/// is shared and every command still takes its locks in the engine's lock
+/// order, and anything outside `Command`, such as mass cancels, mass
+/// quotes, `expire_orders` and session changes, goes to the engine directly
+/// and interleaves with the lanes. A command also runs whatever it sets
+/// off inline on its lane, including stop releases and order group
+/// reactions that may cancel or submit orders for other symbols.
+///
+/// A command that panics fails with an error and its lane carries on.
+pub struct SymbolDispatcher {
+    engine: Arc<MatchingEngine>,
+    lanes: DashMap<String, Sender<Envelope>>,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+    stopped: AtomicBool,
+}
+
+impl SymbolDispatcher {
+    pub fn new(engine: MatchingEngine) -> Self {
+        Self {
+            engine: Arc::new(engine),
+            lanes: DashMap::new(),
+            workers: Mutex::new(Vec::new()),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    pub fn engine(&self) -> &MatchingEngine {
+        &self.engine
+    }
+
+    /// The command channel of `symbol`, starting its lane on first use.
+    fn lane(&self, symbol: &str) -> Result<Sender<Envelope>, String> {
+        if self.stopped.load(Ordering::Acquire) {
+            return Err(format!("Dispatcher for {} has stopped", symbol));
+        }
+        if let Some(lane) = self.lanes.get(symbol) {
+            return Ok(lane.clone());
+        }
+        if self.engine.get_instrument(symbol).is_none() {
+            return Err(format!("Unknown symbol {}", symbol));
+        }
+
+        // Checked again under the workers lock, so no lane can start once
+        // `shutdown` has begun
+        let mut workers = self.workers.lock().unwrap();
+        if self.stopped.load(Ordering::Acquire) {
+            return Err(format!("Dispatcher for {} has stopped", symbol));
+        }
+
+        let lane = self.lanes.entry(symbol.to_string()).or_insert_with(|| {
+            let (tx, rx) = unbounded::<Envelope>();
+            let engine = Arc::clone(&self.engine);
+
+            let worker = std::thread::Builder::new()
+                .name(format!("dispatch-{}", symbol))
+                .spawn(move || {
+                    for (command, reply) in rx {
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| apply(&engine, command)));
+                        // The caller may have given up waiting
+                        let _ = reply.send(result.map_err(|_| "Command panicked".to_string()));
+                    }
+                })
+                .expect("failed to spawn dispatch thread");
+
+            workers.push(worker);
+            tx
+        });
+
+        Ok(lane.clone())
+    }
+
+    /// Stops accepting commands and waits for every queued command to be
+    /// applied.
+    pub fn shutdown(&self) {
+        self.stopped.store(true, Ordering::Release);
+        // Wait for any lane being started to be registered
+        drop(self.workers.lock().unwrap());
+        self.lanes.clear();
+        for worker in self.workers.lock().unwrap().drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl OrderEntry for SymbolDispatcher {
+    /// Queues `command` on the lane for `symbol` and waits for it to be
+    /// applied.
+    fn execute(&self, symbol: &str, command: Command) -> Result<CommandResult, String> {
+        let lane = self.lane(symbol)?;
+        let (reply_tx, reply_rx) = bounded(1);
+
+        if lane.send((command, reply_tx)).is_err() {
+            // A lane whose thread has died is dropped, so the next command starts a new one
+            self.lanes.remove_if(symbol, |_, current| current.same_channel(&lane));
+            return Err(format!("Dispatcher for {} has stopped", symbol));
+        }
+        reply_rx
+            .recv()
+            .map_err(|_| format!("Dispatcher for {} has stopped", symbol))?
+            .map_err(|e| format!("{} for {}", e, symbol))
+    }
+
+    fn symbol_of(&self, order_id: Uuid) -> Result<String, String> {
+        self.engine
+            .get_order(order_id)
+            .map(|order| order.symbol)
+            .ok_or_else(|| "Order not found".to_string())
+    }
+}
+
+impl Drop for SymbolDispatcher {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+pub(super) fn apply(engine: &MatchingEngine, command: Command) -> CommandResult {
+    match command {
+        Command::Submit(order) => CommandResult::Submitted(engine.submit_order(*order)),
+        Command::Cancel(order_id) => CommandResult::Cancelled(engine.cancel_order(order_id)),
+        Command::Amend { order_id, quantity, price } => {
+            CommandResult::Amended(engine.amend_order(order_id, quantity, price))
+        }
+        Command::Replace { order_id, replacement } => {
+            CommandResult::Replaced(engine.replace_order(order_id, *replacement))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{MatchingAlgorithm, PriceTime, RestingOrder};
+    use crate::models::{Instrument, OrderSide, OrderStatus, OrderType, Qty};
+    use rust_decimal_macros::dec;
+
+    fn dispatcher() -> SymbolDispatcher {
+        let engine = MatchingEngine::new();
+        for symbol in ["AAPL", "MSFT"] {
+            engine.register_instrument(Instrument::new(symbol, dec!(0.01), "USD"));
+        }
+        SymbolDispatcher::new(engine)
+    }
+
+    fn limit_order(symbol: &str, side: OrderSide, quantity: Decimal, price: Decimal, user: &str) -> Order {
+        Order::new(
+            symbol.to_string(),
+            side,
+            OrderType::Limit,
+            quantity,
+            Some(price),
+            None,
+            user.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_commands_are_applied_in_order() {
+        let dispatcher = dispatcher();
+        let ask = limit_order("AAPL", OrderSide::Sell, dec!(100), dec!(100.00), "seller");
+        let ask_id = ask.id;
+
+        assert!(dispatcher.submit_order(ask).unwrap().is_empty());
+        dispatcher.amend_order(ask_id, Some(dec!(60)), None).unwrap();
+
+        let trades = dispatcher
+            .submit_order(limit_order("AAPL", OrderSide::Buy, dec!(100), dec!(100.00), "buyer"))
+            .unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, dec!(60));
+        assert!(dispatcher.cancel_order(ask_id).is_err());
+
+        let unknown = limit_order("XYZ", OrderSide::Buy, dec!(1), dec!(1.00), "buyer");
+        assert_eq!(dispatcher.submit_order(unknown).unwrap_err(), "Unknown symbol XYZ");
+
+        let bid = limit_order("MSFT", OrderSide::Buy, dec!(10), dec!(300.00), "buyer");
+        let bid_id = bid.id;
+        dispatcher.submit_order(bid).unwrap();
+        let replacement = limit_order("MSFT", OrderSide::Buy, dec!(20), dec!(299.00), "buyer");
+        dispatcher.replace_order(bid_id, replacement).unwrap();
+        assert_eq!(dispatcher.engine().get_order(bid_id).unwrap().status, OrderStatus::Replaced);
+
+        // No lane is started again once the dispatcher has stopped
+        dispatcher.shutdown();
+        let late = limit_order("AAPL", OrderSide::Buy, dec!(1), dec!(100.00), "buyer");
+        assert_eq!(dispatcher.submit_order(late).unwrap_err(), "Dispatcher for AAPL has stopped");
+    }
+
+    #[test]
+    fn test_lane_survives_a_panicking_command() {
+        struct Panics;
+        impl MatchingAlgorithm for Panics {
+            fn allocate(&self, _quantity: Qty, _resting: &[RestingOrder]) -> Vec<Qty> {
+                panic!("allocation failed");
+            }
+        }
+
+        let dispatcher = dispatcher();
+        dispatcher
+            .submit_order(limit_order("AAPL", OrderSide::Sell, dec!(10), dec!(100.00), "seller"))
+            .unwrap();
+
+        dispatcher.engine().set_matching_algorithm("AAPL", Panics);
+        let buy = || limit_order("AAPL", OrderSide::Buy, dec!(10), dec!(100.00), "buyer");
+        assert_eq!(dispatcher.submit_order(buy()).unwrap_err(), "Command panicked for AAPL");
+
+        // Nothing was committed and the lane keeps working
+        dispatcher.engine().set_matching_algorithm("AAPL", PriceTime);
+        assert_eq!(dispatcher.submit_order(buy()).unwrap().len(), 1);
+        dispatcher.engine().get_orderbook("AAPL").unwrap().verify_invariants().unwrap();
+    }
+
+    /// Submits `count` orders for one user across both symbols, cancelling
+    /// every third one it has resting, and returns every order id submitted.
+    fn trade_and_cancel(dispatcher: &SymbolDispatcher, thread: usize, count: usize) -> Vec<Uuid> {
+        let user = format!("user{}", thread);
+        let mut submitted = Vec::new();
+        let mut resting = Vec::new();
+
+        for i in 0..count {
+            let symbol = if i.is_multiple_of(2) { "AAPL" } else { "MSFT" };
+            let side = if (thread + i).is_multiple_of(2) { OrderSide::Buy } else { OrderSide::Sell };
+            let price = dec!(100.00) + Decimal::new(((thread * 7 + i * 3) % 11) as i64 - 5, 2);
+            let order = limit_order(symbol, side, Decimal::from(1 + i % 5), price, &user);
+
+            resting.push(order.id);
+            submitted.push(order.id);
+            dispatcher.submit_order(order).unwrap();
+
+            // The cancel races fills from the other threads and may find the order gone
+            if i % 3 == 0 {
+                let victim = resting.swap_remove((i * 31) % resting.len());
+                let _ = dispatcher.cancel_order(victim);
+            }
+        }
+
+        submitted
+    }
+
+    #[test]
+    fn test_concurrent_submit_and_cancel() {
+        let dispatcher = dispatcher();
+        let (threads, per_thread) = (8, 250);
+
+        let submitted: Vec<Uuid> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads)
+                .map(|thread| {
+                    let dispatcher = &dispatcher;
+                    scope.spawn(move || trade_and_cancel(dispatcher, thread, per_thread))
+                })
+                .collect();
+
+            handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+        });
+        assert_eq!(submitted.len(), threads * per_thread);
+
+        let engine = dispatcher.engine();
+        for symbol in ["AAPL", "MSFT"] {
+            let book = engine.get_orderbook(symbol).unwrap();
+            book.verify_invariants().unwrap();
+
+            // Whatever still rests is working and the book is not crossed
+            for level in book.bids.values().chain(book.asks.values()) {
+                for entry in book.queue(level) {
+                    let order = engine.get_order(entry.order_id).unwrap();
+                    assert!(matches!(order.status, OrderStatus::Pending | OrderStatus::PartiallyFilled));
+                    assert_eq!(book.qty_value(entry.remaining), order.remaining_quantity());
+                }
+            }
+            if let (Some(bid), Some(ask)) = (book.best_bid(), book.best_ask()) {
+                assert!(bid < ask, "{} crossed at {} / {}", symbol, bid, ask);
+            }
+
+            // Every fill has a buyer and a seller
+            let filled = |side| -> Decimal {
+                submitted
+                    .iter()
+                    .filter_map(|id| engine.get_order(*id))
+                    .filter(|order| order.symbol == symbol && order.side == side)
+                    .map(|order| order.filled_quantity)
+                    .sum()
+            };
+            assert_eq!(filled(OrderSide::Buy), filled(OrderSide::Sell));
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::{
+    AuctionKind, AuctionSummary, BandBreach, SelfTradePrevention, SessionState, Uncross,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EngineEvent {
+    StopTriggered {
+        symbol: String,
+        order_id: Uuid,
+        stop_price: Decimal,
+        last_price: Decimal,
+        timestamp: DateTime<Utc>,
+    },
+    PegRepriced {
+        symbol: String,
+        order_id: Uuid,
+        old_price: Decimal,
+        new_price: Decimal,
+        timestamp: DateTime<Utc>,
+    },
+    SelfTradePrevented {
+        symbol: String,
+        mode: SelfTradePrevention,
+        aggressing_order_id: Uuid,
+        resting_order_id: Uuid,
+        quantity: Decimal,
+        timestamp: DateTime<Utc>,
+    },
+    OrderExpired {
+        symbol: String,
+        order_id: Uuid,
+        timestamp: DateTime<Utc>,
+    },
+    /// Indicative uncrossing during an auction call, `None` while nothing crosses
+    AuctionIndicative {
+        symbol: String,
+        kind: AuctionKind,
+        uncross: Option<Uncross>,
+        timestamp: DateTime<Utc>,
+    },
+    AuctionUncrossed {
+        summary: AuctionSummary,
+    },
+    SessionChanged {
+        symbol: String,
+        from: SessionState,
+        to: SessionState,
+        timestamp: DateTime<Utc>,
+    },
+    /// An order tried to trade outside the price band and halted the symbol
+    VolatilityHalt {
+        symbol: String,
+        order_id: Uuid,
+        breach: BandBreach,
+        timestamp: DateTime<Utc>,
+    },
+}
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::{Order, OrderSide};
+
+/// Selects the working orders pulled by `MatchingEngine::mass_cancel`.
+/// Unset fields match everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MassCancelFilter {
+    pub user_id: Option<String>,
+    pub symbol: Option<String>,
+    pub side: Option<OrderSide>,
+}
+
+impl MassCancelFilter {
+    pub fn with_user(mut self, user_id: &str) -> Self {
+        self.user_id = Some(user_id.to_string());
+        self
+    }
+
+    pub fn with_symbol(mut self, symbol: &str) -> Self {
+        self.symbol = Some(symbol.to_string());
+        self
+    }
+
+    pub fn with_side(mut self, side: OrderSide) -> Self {
+        self.side = Some(side);
+        self
+    }
+
+    pub fn matches(&self, order: &Order) -> bool {
+        order.is_active()
+            && self.user_id.as_ref().is_none_or(|user_id| *user_id == order.user_id)
+            && self.symbol.as_ref().is_none_or(|symbol| *symbol == order.symbol)
+            && self.side.is_none_or(|side| side == order.side)
+    }
+}
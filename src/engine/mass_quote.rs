@@ -0,0 +1,118 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::{Order, OrderSide, OrderType, Trade};
+
+/// One symbol of a mass quote. A side left out pulls that side of the
+/// maker's current quote.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuoteEntry {
+    pub symbol: String,
+    /// Price and quantity
+    pub bid: Option<(Decimal, Decimal)>,
+    pub ask: Option<(Decimal, Decimal)>,
+}
+
+impl QuoteEntry {
+    pub fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            bid: None,
+            ask: None,
+        }
+    }
+
+    pub fn with_bid(mut self, price: Decimal, quantity: Decimal) -> Self {
+        self.bid = Some((price, quantity));
+        self
+    }
+
+    pub fn with_ask(mut self, price: Decimal, quantity: Decimal) -> Self {
+        self.ask = Some((price, quantity));
+        self
+    }
+
+    /// The limit orders this entry places for `user_id`, bid first.
+    pub(crate) fn orders(&self, user_id: &str) -> Vec<Order> {
+        let order = |side, (price, quantity)| {
+            Order::new(
+                self.symbol.clone(),
+                side,
+                OrderType::Limit,
+                quantity,
+                Some(price),
+                None,
+                user_id.to_string(),
+            )
+        };
+
+        [
+            self.bid.map(|bid| order(OrderSide::Buy, bid)),
+            self.ask.map(|ask| order(OrderSide::Sell, ask)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+/// A market maker's live two-sided quote on one symbol. Every accepted
+/// replace gets a new quote id.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MakerQuote {
+    pub quote_id: Uuid,
+    pub user_id: String,
+    pub symbol: String,
+    pub bid_order_id: Option<Uuid>,
+    pub ask_order_id: Option<Uuid>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl MakerQuote {
+    pub fn order_ids(&self) -> impl Iterator<Item = Uuid> {
+        self.bid_order_id.into_iter().chain(self.ask_order_id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuoteStatus {
+    Accepted,
+    /// The maker's previous quote on the symbol is left as it was
+    Rejected(String),
+}
+
+/// The outcome of one entry of a mass quote.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuoteAck {
+    pub quote_id: Uuid,
+    pub symbol: String,
+    pub status: QuoteStatus,
+    /// Trades the new quote made on entry
+    pub trades: Vec<Trade>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_quote_entry_orders() {
+        let entry = QuoteEntry::new("AAPL")
+            .with_bid(dec!(99.99), dec!(100))
+            .with_ask(dec!(100.01), dec!(200));
+
+        let orders = entry.orders("maker");
+        assert_eq!(orders.len(), 2);
+        let sides: Vec<_> = orders.iter().map(|order| (order.side, order.price, order.quantity)).collect();
+        assert_eq!(sides, vec![
+            (OrderSide::Buy, Some(dec!(99.99)), dec!(100)),
+            (OrderSide::Sell, Some(dec!(100.01)), dec!(200)),
+        ]);
+        assert!(orders.iter().all(|order| order.user_id == "maker"));
+
+        assert!(QuoteEntry::new("AAPL").orders("maker").is_empty());
+    }
+}
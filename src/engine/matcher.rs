@@ -0,0 +1,356 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+use uuid::Uuid;
+
+use super::algorithms::{MatchingAlgorithm, RestingOrder};
+use crate::models::{BandBreach, Order, OrderBook, OrderSide, Price, Qty, Rounding, SelfTradePrevention, Trade};
+
+/// One decision taken while walking the book, in execution order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MatchStep {
+    Fill {
+        resting_order_id: Uuid,
+        price: Price,
+        quantity: Qty,
+    },
+    Prevent {
+        resting_order_id: Uuid,
+        mode: SelfTradePrevention,
+        quantity: Qty,
+    },
+}
+
+impl MatchStep {
+    /// How much of the incoming order the step uses up.
+    fn taken(&self) -> Qty {
+        match *self {
+            MatchStep::Fill { quantity, .. } => quantity,
+            MatchStep::Prevent {
+                mode: SelfTradePrevention::DecrementAndCancel,
+                quantity,
+                ..
+            } => quantity,
+            MatchStep::Prevent { .. } => Qty::ZERO,
+        }
+    }
+}
+
+/// What matching would do to an incoming order, worked out against a book
+/// without modifying it. Committing a plan replays the steps in order.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MatchPlan {
+    pub steps: Vec<MatchStep>,
+    /// Quantity left neither filled nor removed by self-trade prevention
+    pub remaining: Qty,
+    /// Set when a fill-or-kill order could not fill completely
+    pub killed: bool,
+    /// Set when matching was cut short at a price outside the price band
+    pub breach: Option<BandBreach>,
+}
+
+impl MatchPlan {
+    pub fn unmatched(remaining: Qty) -> Self {
+        Self {
+            steps: Vec::new(),
+            remaining,
+            killed: false,
+            breach: None,
+        }
+    }
+
+    pub fn killed(remaining: Qty) -> Self {
+        Self {
+            killed: true,
+            ..Self::unmatched(remaining)
+        }
+    }
+
+    /// Drops every step from `len` on, leaving the order with whatever
+    /// those steps would have taken.
+    pub fn truncate(&mut self, len: usize) {
+        let dropped: Qty = self.steps.drain(len..).map(|step| step.taken()).sum();
+        self.remaining += dropped;
+    }
+
+    pub fn trades(&self, order: &Order, book: &OrderBook) -> Vec<Trade> {
+        self.steps
+            .iter()
+            .filter_map(|step| match *step {
+                MatchStep::Fill { resting_order_id, price, quantity } => Some(trade_between(
+                    order,
+                    resting_order_id,
+                    book.price_value(price),
+                    book.qty_value(quantity),
+                )),
+                MatchStep::Prevent { .. } => None,
+            })
+            .collect()
+    }
+}
+
+/// The result of a dry-run submission.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulationResult {
+    pub trades: Vec<Trade>,
+    pub filled_quantity: Decimal,
+    pub average_price: Option<Decimal>,
+    pub leftover_quantity: Decimal,
+}
+
+impl SimulationResult {
+    pub(crate) fn from_plan(order: &Order, plan: &MatchPlan, book: &OrderBook) -> Self {
+        let trades = plan.trades(order, book);
+        let filled_quantity: Decimal = trades.iter().map(|t| t.quantity).sum();
+        let notional: Decimal = trades.iter().map(|t| t.notional_value()).sum();
+
+        Self {
+            average_price: (filled_quantity > Decimal::ZERO).then(|| notional / filled_quantity),
+            filled_quantity,
+            leftover_quantity: book.qty_value(plan.remaining),
+            trades,
+        }
+    }
+}
+
+pub(crate) fn trade_between(order: &Order, resting_order_id: Uuid, price: Decimal, quantity: Decimal) -> Trade {
+    let (buyer_id, seller_id) = match order.side {
+        OrderSide::Buy => (order.id, resting_order_id),
+        OrderSide::Sell => (resting_order_id, order.id),
+    };
+
+    Trade::new(order.symbol.clone(), buyer_id, seller_id, price, quantity, order.side)
+}
+
+/// Whether a resting price is marketable for an order on `side` limited at
+/// `limit`. Orders without a limit cross at any price.
+pub(crate) fn crosses<P: PartialOrd>(side: OrderSide, price: P, limit: Option<P>) -> bool {
+    match (side, limit) {
+        (_, None) => true,
+        (OrderSide::Buy, Some(limit)) => price <= limit,
+        (OrderSide::Sell, Some(limit)) => price >= limit,
+    }
+}
+
+/// A resting order as seen by the planner: its displayed slice and the
+/// reserve behind it.
+struct Queued {
+    order_id: Uuid,
+    user_id: String,
+    shown: Qty,
+    reserve: Qty,
+    display_quantity: Option<Qty>,
+    hidden: bool,
+    min_quantity: Option<Qty>,
+    all_or_none: bool,
+}
+
+impl Queued {
+    /// Mirrors `Order::min_execution` as the plan fills the order, capped
+    /// at the displayed slice so icebergs can still trade.
+    fn min_execution(&self) -> Qty {
+        let remaining = self.shown + self.reserve;
+        let min = if self.all_or_none {
+            remaining
+        } else {
+            self.min_quantity.map_or(Qty::ZERO, |min| min.min(remaining))
+        };
+        min.min(self.shown)
+    }
+}
+
+/// A minimum execution quantity in whole lots. A minimum between two lots
+/// needs the larger one, and one too large to represent can never be met.
+fn min_lots(book: &OrderBook, min_quantity: Option<Decimal>) -> Option<Qty> {
+    min_quantity.map(|min| book.to_qty(min, Rounding::Up).unwrap_or(Qty::new(i64::MAX)))
+}
+
+/// Asks `algorithm` to split `quantity` over the displayed slices in
+/// `queue`, in queue order. An allocation smaller than the resting order's
+/// or the incoming order's minimum execution takes that resting order out
+/// of the split, and the rest is allocated again without it, so everyone
+/// else keeps their priority.
+fn allocate(
+    algorithm: &dyn MatchingAlgorithm,
+    quantity: Qty,
+    queue: &[Queued],
+    incoming_min_quantity: Option<Qty>,
+) -> Vec<Qty> {
+    let incoming_min = incoming_min_quantity.map_or(Qty::ZERO, |min| min.min(quantity));
+    let mut excluded = vec![false; queue.len()];
+
+    loop {
+        let resting: Vec<RestingOrder> = queue
+            .iter()
+            .zip(&excluded)
+            .map(|(queued, excluded)| RestingOrder {
+                order_id: queued.order_id,
+                user_id: &queued.user_id,
+                quantity: if *excluded { Qty::ZERO } else { queued.shown },
+            })
+            .collect();
+
+        // Allocations are held to what each order has available and to
+        // `quantity` overall, so an algorithm breaking its contract can neither
+        // overfill nor hand lots back to an excluded order and retry forever
+        let mut left = quantity;
+        let allocations: Vec<Qty> = resting
+            .iter()
+            .zip(algorithm.allocate(quantity, &resting).into_iter().chain(std::iter::repeat(Qty::ZERO)))
+            .map(|(order, allocated)| {
+                let allocated = allocated.max(Qty::ZERO).min(order.quantity).min(left);
+                left -= allocated;
+                allocated
+            })
+            .collect();
+        let unacceptable = allocations.iter().zip(queue).position(|(allocated, queued)| {
+            *allocated > Qty::ZERO && (*allocated < queued.min_execution() || *allocated < incoming_min)
+        });
+
+        match unacceptable {
+            Some(index) => excluded[index] = true,
+            None => return allocations,
+        }
+    }
+}
+
+/// Walks the opposite side of `book` level by level and records the fills
+/// and self-trade preventions `order` would cause. Within a level the
+/// quantity is split by `algorithm` in rounds over the displayed slices;
+/// icebergs exhausted in a round refresh behind the rest of the level for
+/// the next one, following the same rules `OrderBook::fill_order` applies
+/// when the plan is committed. `quantity` is what is left of `order` and
+/// `limit` its limit, both already on the book's grid.
+pub(crate) fn plan_match<F, R>(
+    book: &OrderBook,
+    order: &Order,
+    quantity: Qty,
+    limit: Option<Price>,
+    algorithm: &dyn MatchingAlgorithm,
+    default_self_trade_prevention: Option<SelfTradePrevention>,
+    lookup: F,
+) -> MatchPlan
+where
+    F: Fn(&Uuid) -> Option<R>,
+    R: Deref<Target = Order>,
+{
+    let mut steps = Vec::new();
+    let mut remaining = quantity;
+    let incoming_min_quantity = min_lots(book, order.min_quantity);
+    let self_trade_prevention = order.self_trade_prevention.or(default_self_trade_prevention);
+
+    let levels: Box<dyn Iterator<Item = _>> = match order.side {
+        OrderSide::Buy => Box::new(book.asks.values()),
+        OrderSide::Sell => Box::new(book.bids.values().rev()),
+    };
+
+    'levels: for level in levels {
+        if remaining <= Qty::ZERO || !crosses(order.side, level.price, limit) {
+            break;
+        }
+
+        let mut queue: Vec<Queued> = book
+            .queue(level)
+            .filter_map(|entry| {
+                let resting = lookup(&entry.order_id)?;
+                Some(Queued {
+                    order_id: entry.order_id,
+                    user_id: resting.user_id.clone(),
+                    shown: entry.quantity,
+                    reserve: entry.remaining - entry.quantity,
+                    display_quantity: entry.display,
+                    hidden: entry.hidden,
+                    min_quantity: min_lots(book, resting.min_quantity),
+                    all_or_none: resting.all_or_none,
+                })
+            })
+            .collect();
+
+        while remaining > Qty::ZERO && queue.iter().any(|queued| queued.shown > Qty::ZERO) {
+            let mut allocations = allocate(algorithm, remaining, &queue, incoming_min_quantity);
+            // Nothing here can trade with this order; try the next level
+            if allocations.iter().all(|allocated| allocated.is_zero()) {
+                break;
+            }
+            let mut index = 0;
+
+            while index < queue.len() && remaining > Qty::ZERO {
+                let allocated = allocations[index];
+                if allocated <= Qty::ZERO {
+                    index += 1;
+                    continue;
+                }
+
+                let resting = &mut queue[index];
+
+                if let (true, Some(mode)) = (resting.user_id == order.user_id, self_trade_prevention) {
+                    let quantity = remaining.min(resting.shown + resting.reserve);
+                    steps.push(MatchStep::Prevent {
+                        resting_order_id: resting.order_id,
+                        mode,
+                        quantity,
+                    });
+
+                    match mode {
+                        SelfTradePrevention::CancelResting => {
+                            queue.remove(index);
+                        }
+                        SelfTradePrevention::CancelAggressing | SelfTradePrevention::CancelBoth => {
+                            break 'levels;
+                        }
+                        SelfTradePrevention::DecrementAndCancel => {
+                            remaining -= quantity;
+                            let left = resting.shown + resting.reserve - quantity;
+                            if left > Qty::ZERO {
+                                resting.shown = resting.shown.min(left);
+                                resting.reserve = left - resting.shown;
+                                index += 1;
+                            } else {
+                                queue.remove(index);
+                            }
+                        }
+                    }
+
+                    // Whatever is left goes to the orders not yet reached this round
+                    let mut reallocated = vec![Qty::ZERO; index];
+                    reallocated.extend(allocate(algorithm, remaining, &queue[index..], incoming_min_quantity));
+                    allocations = reallocated;
+                    continue;
+                }
+
+                steps.push(MatchStep::Fill {
+                    resting_order_id: resting.order_id,
+                    price: level.price,
+                    quantity: allocated,
+                });
+                remaining -= allocated;
+                resting.shown -= allocated;
+                index += 1;
+            }
+
+            // Exhausted slices refresh from the reserve at the back of the
+            // displayed orders, still ahead of hidden ones
+            let (live, exhausted): (Vec<Queued>, Vec<Queued>) =
+                queue.into_iter().partition(|queued| queued.shown > Qty::ZERO);
+            let (lit, dark): (Vec<Queued>, Vec<Queued>) = live.into_iter().partition(|queued| !queued.hidden);
+            queue = lit;
+            queue.extend(exhausted.into_iter().filter(|queued| queued.reserve > Qty::ZERO).map(
+                |mut queued| {
+                    queued.shown = queued
+                        .display_quantity
+                        .map_or(queued.reserve, |display| display.min(queued.reserve));
+                    queued.reserve -= queued.shown;
+                    queued
+                },
+            ));
+            queue.extend(dark);
+        }
+    }
+
+    MatchPlan {
+        steps,
+        remaining,
+        killed: false,
+        breach: None,
+    }
+}
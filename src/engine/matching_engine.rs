@@ -1,313 +1,3356 @@
+use chrono::{DateTime, Utc};
+use crossbeam::channel::{unbounded, Receiver, Sender};
 use dashmap::DashMap;
 use rust_decimal::Decimal;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::sync::Arc;
+use tracing::warn;
 use uuid::Uuid;
 
-use crate::models::{Order, OrderBook, OrderSide, OrderStatus, OrderType, Trade};
+use super::algorithms::{MatchingAlgorithm, PriceTime};
+use super::matcher::{crosses, plan_match, trade_between, MatchPlan, MatchStep};
+use super::{
+    EngineEvent, MakerQuote, MassCancelFilter, QuoteAck, QuoteEntry, QuoteStatus, SimulationResult, SymbolState,
+};
+use crate::models::fixed::common_unit;
+use crate::models::orderbook::PriceLevel;
+use crate::models::{
+    AuctionKind, AuctionSummary, BandAction, BandBreach, GroupKind, GroupStatus, Instrument, Order,
+    OrderBook, OrderGroup, OrderSide, OrderStatus, OrderType, Peg, PostOnly, Price, PriceBands, Qty, Rounding,
+    SelfTradePrevention, SessionState, StopBook, TimeInForce, Timetable, Trade, TrailReference,
+    TrailingStop, Uncross,
+};
+
+/// An order taking part in an auction uncross.
+struct AuctionOrder {
+    order_id: Uuid,
+    remaining: Qty,
+    /// Least the order accepts if it trades at all
+    min_execution: Qty,
+    timestamp: DateTime<Utc>,
+}
+
+/// One trade of an auction uncross.
+struct AuctionFill {
+    buy_order_id: Uuid,
+    sell_order_id: Uuid,
+    quantity: Qty,
+    aggressor: OrderSide,
+}
 
 pub struct MatchingEngine {
     orderbooks: Arc<DashMap<String, OrderBook>>,
+    stop_books: Arc<DashMap<String, StopBook>>,
     orders: Arc<DashMap<Uuid, Order>>,
+    instruments: Arc<DashMap<String, Instrument>>,
+    algorithms: Arc<DashMap<String, Arc<dyn MatchingAlgorithm>>>,
+    sessions: Arc<DashMap<String, SessionState>>,
+    price_bands: Arc<DashMap<String, PriceBands>>,
+    /// Client sessions registered for cancel-on-disconnect, by session id
+    cancel_on_disconnect: Arc<DashMap<String, String>>,
+    groups: Arc<DashMap<Uuid, OrderGroup>>,
+    /// Group of every order that belongs to one, including held children
+    group_legs: Arc<DashMap<Uuid, Uuid>>,
+    /// Live market maker quotes by user and symbol
+    quotes: Arc<DashMap<(String, String), MakerQuote>>,
+    /// Each timetable with the scheduled state it last applied
+    timetables: Arc<DashMap<String, (Timetable, Option<SessionState>)>>,
+    events_tx: Sender<EngineEvent>,
+    events_rx: Receiver<EngineEvent>,
+    default_self_trade_prevention: Option<SelfTradePrevention>,
 }
 
 impl MatchingEngine {
     pub fn new() -> Self {
+        let (events_tx, events_rx) = unbounded();
         Self {
             orderbooks: Arc::new(DashMap::new()),
+            stop_books: Arc::new(DashMap::new()),
             orders: Arc::new(DashMap::new()),
+            instruments: Arc::new(DashMap::new()),
+            algorithms: Arc::new(DashMap::new()),
+            sessions: Arc::new(DashMap::new()),
+            price_bands: Arc::new(DashMap::new()),
+            cancel_on_disconnect: Arc::new(DashMap::new()),
+            groups: Arc::new(DashMap::new()),
+            group_legs: Arc::new(DashMap::new()),
+            quotes: Arc::new(DashMap::new()),
+            timetables: Arc::new(DashMap::new()),
+            events_tx,
+            events_rx,
+            default_self_trade_prevention: None,
         }
     }
 
-    pub fn submit_order(&self, mut order: Order) -> Result<Vec<Trade>, String> {
-        order.validate()?;
+    /// Sets the self-trade prevention mode used for orders that do not
+    /// specify their own.
+    pub fn with_self_trade_prevention(mut self, mode: SelfTradePrevention) -> Self {
+        self.default_self_trade_prevention = Some(mode);
+        self
+    }
+
+    /// Submits an order and returns every trade it caused, including trades
+    /// from stop orders released by the resulting price move.
+    pub fn submit_order(&self, order: Order) -> Result<Vec<Trade>, String> {
+        self.validate_order(&order)?;
 
         let symbol = order.symbol.clone();
-        
-        // Ensure orderbook exists
-        if !self.orderbooks.contains_key(&symbol) {
-            self.orderbooks.insert(symbol.clone(), OrderBook::new(symbol.clone()));
+        let order_id = order.id;
+        let session = self.session_state(&symbol);
+        if !session.accepts_orders() {
+            return Err(format!("Orders are not accepted for {} while {:?}", symbol, session));
         }
 
-        let mut trades = Vec::new();
+        let mut order = order;
+        self.prepare_entry(&mut order)?;
 
-        // Handle market orders immediately
-        if order.order_type == OrderType::Market {
-            trades = self.match_market_order(&mut order)?;
+        let mut trades = if order.is_stop() {
+            // Park stop orders until the last trade price crosses their stop
+            self.stop_books.get_mut(&symbol).unwrap().add_order(&order);
+            self.orders.insert(order.id, order);
+            Vec::new()
         } else {
-            // Try to match limit orders
-            trades = self.match_limit_order(&mut order)?;
-        }
+            self.execute_order(order)?
+        };
 
-        // If order is not fully filled, add to orderbook
-        if !order.is_fully_filled() && order.status != OrderStatus::Cancelled {
-            let mut book = self.orderbooks.get_mut(&symbol).unwrap();
-            book.add_order(&order);
-        }
+        trades.extend(self.release_stop_orders(&symbol));
+        self.reprice_pegged_orders(&symbol);
+        self.publish_indicative(&symbol);
 
-        // Store order
-        self.orders.insert(order.id, order);
+        let touched = Self::traded_order_ids(&trades).chain([order_id]).collect::<Vec<_>>();
+        trades.extend(self.reconcile_groups(touched));
 
         Ok(trades)
     }
 
-    fn match_market_order(&self, order: &mut Order) -> Result<Vec<Trade>, String> {
-        let mut trades = Vec::new();
+    /// Sets the prices a new order takes from the market: trailing stops
+    /// without an explicit stop start from their reference, and pegged orders
+    /// enter at their current peg price like any limit order.
+    fn prepare_entry(&self, order: &mut Order) -> Result<(), String> {
+        if let (true, Some(trailing), None) = (order.is_stop(), order.trailing_stop, order.stop_price) {
+            let stop_price = self
+                .trailing_stop_price(order, trailing)
+                .ok_or("No reference price for trailing stop")?;
+            order.trail_stop_to(stop_price);
+        }
+        if !order.is_stop() && order.peg.is_some() {
+            let price = self
+                .peg_price(order)
+                .ok_or("No reference price for pegged order")?;
+            order.price = Some(price);
+        }
+
+        Ok(())
+    }
+
+    /// Matches an order and rests whatever may rest. The whole match is
+    /// planned against the book first and only then committed, under a
+    /// single book lock, so a rejected order never leaves partial effects.
+    fn execute_order(&self, mut order: Order) -> Result<Vec<Trade>, String> {
         let symbol = order.symbol.clone();
-        
         let mut book = self.orderbooks.get_mut(&symbol).unwrap();
-        
-        let opposite_side = match order.side {
-            OrderSide::Buy => OrderSide::Sell,
-            OrderSide::Sell => OrderSide::Buy,
+
+        let plan = match self.plan_order(&book, &mut order, &[]) {
+            Ok(plan) => plan,
+            Err(e) => {
+                order.reject();
+                drop(book);
+                self.orders.insert(order.id, order);
+                return Err(e);
+            }
         };
 
-        let levels: Vec<(Decimal, Vec<Uuid>)> = match opposite_side {
-            OrderSide::Buy => book.bids.iter().rev()
-                .map(|(price, level)| (*price, level.orders.clone()))
-                .collect(),
-            OrderSide::Sell => book.asks.iter()
-                .map(|(price, level)| (*price, level.orders.clone()))
-                .collect(),
+        let trades = self.commit_order(&mut book, &mut order, &plan);
+
+        // Store order
+        drop(book);
+        let order_id = order.id;
+        self.orders.insert(order_id, order);
+
+        if let Some(breach) = plan.breach {
+            self.volatility_halt(&symbol, order_id, breach);
+        }
+
+        Ok(trades)
+    }
+
+    /// Decides everything matching will do for `order` without changing any
+    /// state. Shared by real submissions and `simulate_order`. Resting
+    /// orders in `excluded` are treated as already gone from the book.
+    fn plan_order(&self, book: &OrderBook, order: &mut Order, excluded: &[Uuid]) -> Result<MatchPlan, String> {
+        let quantity = book.to_qty(order.remaining_quantity(), Rounding::Exact)?;
+        // Whatever is left may rest, so the price has to fit the book
+        if let Some(price) = order.price {
+            book.passive_price(order.side, price)?;
+        }
+
+        // During an auction call orders only rest; they trade at the uncross
+        if book.auction.is_some() {
+            if order.order_type != OrderType::Limit || order.peg.is_some() {
+                return Err("Only plain limit orders are accepted during an auction call".to_string());
+            }
+            if matches!(order.time_in_force, TimeInForce::Ioc | TimeInForce::Fok) {
+                return Err("Immediate orders are not accepted during an auction call".to_string());
+            }
+            return Ok(MatchPlan::unmatched(quantity));
+        }
+
+        let limit = match order.order_type {
+            OrderType::Market | OrderType::StopLoss => order.protection_price,
+            OrderType::Limit | OrderType::StopLimit => order.price,
         };
 
-        for (price, order_ids) in levels {
-            if order.is_fully_filled() {
-                break;
+        // Post-only orders never take liquidity: reject or slide behind the touch
+        if let Some(post_only) = order.post_only {
+            let touch = book
+                .front(order.side.opposite())
+                .map(|(price, _)| price)
+                .filter(|price| crosses(order.side, *price, limit));
+
+            if let Some(touch) = touch {
+                let tick_size = self.tick_size(book, touch);
+                let slide_price = match order.side {
+                    OrderSide::Buy => touch - tick_size,
+                    OrderSide::Sell => touch + tick_size,
+                };
+
+                match post_only {
+                    PostOnly::Slide if slide_price > Decimal::ZERO => {
+                        order.price = Some(slide_price);
+                    }
+                    _ => return Err("Post-only order would take liquidity".to_string()),
+                }
             }
 
-            for order_id in order_ids {
-                if order.is_fully_filled() {
-                    break;
+            return Ok(MatchPlan::unmatched(quantity));
+        }
+
+        let algorithm = self
+            .algorithms
+            .get(&order.symbol)
+            .map(|algorithm| Arc::clone(&algorithm))
+            .unwrap_or_else(|| Arc::new(PriceTime));
+
+        // A limit between two grid prices trades only as far as the one behind it
+        let limit = limit.map(|limit| book.passive_price(order.side, limit)).transpose()?;
+        let mut plan = plan_match(
+            book,
+            order,
+            quantity,
+            limit,
+            algorithm.as_ref(),
+            self.default_self_trade_prevention,
+            |id| self.orders.get(id).filter(|_| !excluded.contains(id)),
+        );
+
+        // Nothing may trade outside the price band
+        let mut breach = None;
+        if let Some(bands) = self.price_bands.get(&order.symbol) {
+            let outside = plan.steps.iter().enumerate().find_map(|(index, step)| match *step {
+                MatchStep::Fill { price, .. } => {
+                    Some((index, bands.check(book.price_value(price), book.last_trade_price)?))
                 }
+                MatchStep::Prevent { .. } => None,
+            });
 
-                if let Some(mut matching_order) = self.orders.get_mut(&order_id) {
-                    let trade_quantity = order.remaining_quantity().min(matching_order.remaining_quantity());
-                    
-                    let (buyer_id, seller_id) = match order.side {
-                        OrderSide::Buy => (order.id, matching_order.id),
-                        OrderSide::Sell => (matching_order.id, order.id),
-                    };
+            if let Some((index, found)) = outside {
+                if bands.action == BandAction::Reject {
+                    return Err(format!(
+                        "Order would trade at {} outside the price band {:?} to {:?}",
+                        found.price, found.lower, found.upper
+                    ));
+                }
+                plan.truncate(index);
+                breach = Some(found);
+            }
+        }
+
+        // Fill-or-kill orders leave the book untouched unless they fill completely
+        if order.time_in_force == TimeInForce::Fok && plan.remaining > Qty::ZERO {
+            // A killed order never trades, so it cannot halt the symbol either
+            plan = MatchPlan::killed(quantity);
+            breach = None;
+        } else if order.all_or_none && plan.remaining > Qty::ZERO {
+            // All-or-none orders trade in one go or wait on the book
+            plan = MatchPlan::unmatched(quantity);
+            breach = None;
+        }
+
+        plan.breach = breach;
+        Ok(plan)
+    }
+
+    /// Commits `plan` and rests or cancels whatever is left of `order`.
+    fn commit_order(&self, book: &mut OrderBook, order: &mut Order, plan: &MatchPlan) -> Vec<Trade> {
+        let trades = self.commit_match(book, order, plan);
+
+        // Market, immediate-or-cancel and killed fill-or-kill remainders never rest
+        let rests = !plan.killed
+            && matches!(order.order_type, OrderType::Limit | OrderType::StopLimit)
+            && !matches!(order.time_in_force, TimeInForce::Ioc | TimeInForce::Fok);
+
+        if !order.is_fully_filled() && order.is_active() {
+            if !rests {
+                order.cancel();
+            } else if let Err(e) = book.add_order(order) {
+                warn!("Order {} cannot rest: {}", order.id, e);
+                order.cancel();
+            }
+        }
+
+        trades
+    }
 
-                    let trade = Trade::new(
-                        symbol.clone(),
-                        buyer_id,
-                        seller_id,
-                        price,
-                        trade_quantity,
-                        order.side,
-                    );
+    fn commit_match(&self, book: &mut OrderBook, order: &mut Order, plan: &MatchPlan) -> Vec<Trade> {
+        let mut trades = Vec::new();
+
+        for step in &plan.steps {
+            match *step {
+                MatchStep::Fill { resting_order_id, price, quantity } => {
+                    // The book and the order map are always updated together
+                    let Some(mut resting) = self.orders.get_mut(&resting_order_id) else {
+                        continue;
+                    };
 
-                    order.fill(trade_quantity);
-                    matching_order.fill(trade_quantity);
+                    let (price_value, quantity_value) = (book.price_value(price), book.qty_value(quantity));
+                    order.fill(quantity_value);
+                    resting.fill(quantity_value);
+                    book.fill_order(&resting, quantity);
+                    book.last_trade_price = Some(price_value);
 
-                    trades.push(trade);
+                    trades.push(trade_between(order, resting_order_id, price_value, quantity_value));
+                }
+                MatchStep::Prevent { resting_order_id, mode, quantity } => {
+                    let Some(mut resting) = self.orders.get_mut(&resting_order_id) else {
+                        continue;
+                    };
+                    let quantity = book.qty_value(quantity);
 
-                    if matching_order.is_fully_filled() {
-                        book.remove_order(&matching_order);
+                    match mode {
+                        SelfTradePrevention::CancelResting => {
+                            book.remove_order(&resting);
+                            resting.cancel();
+                        }
+                        SelfTradePrevention::CancelAggressing => {
+                            order.cancel();
+                        }
+                        SelfTradePrevention::CancelBoth => {
+                            book.remove_order(&resting);
+                            resting.cancel();
+                            order.cancel();
+                        }
+                        SelfTradePrevention::DecrementAndCancel => {
+                            resting.decrement(quantity);
+                            if resting.status == OrderStatus::Cancelled {
+                                book.remove_order(&resting);
+                            } else if let Err(e) = book.reduce_order(&resting) {
+                                warn!("Order {} could not be reduced: {}", resting.id, e);
+                            }
+                            order.decrement(quantity);
+                        }
                     }
+
+                    self.publish(EngineEvent::SelfTradePrevented {
+                        symbol: order.symbol.clone(),
+                        mode,
+                        aggressing_order_id: order.id,
+                        resting_order_id,
+                        quantity,
+                        timestamp: Utc::now(),
+                    });
                 }
             }
         }
 
-        if !order.is_fully_filled() {
-            order.reject();
-            return Err("Market order could not be fully filled".to_string());
+        trades
+    }
+
+    /// Runs `order` through the same matching logic as `submit_order`
+    /// against the current book and reports the would-be outcome without
+    /// changing any state. Stop orders are evaluated as if just triggered,
+    /// and stops the resulting trades would trigger are not included.
+    pub fn simulate_order(&self, order: &Order) -> Result<SimulationResult, String> {
+        self.validate_order(order)?;
+
+        let mut order = order.clone();
+        if order.peg.is_some() {
+            let price = self
+                .peg_price(&order)
+                .ok_or("No reference price for pegged order")?;
+            order.price = Some(price);
         }
 
-        Ok(trades)
+        let book = self
+            .orderbooks
+            .get(&order.symbol)
+            .ok_or_else(|| format!("Unknown symbol {}", order.symbol))?;
+        let plan = self.plan_order(&book, &mut order, &[])?;
+
+        Ok(SimulationResult::from_plan(&order, &plan, &book))
     }
 
-    fn match_limit_order(&self, order: &mut Order) -> Result<Vec<Trade>, String> {
+    fn release_stop_orders(&self, symbol: &str) -> Vec<Trade> {
         let mut trades = Vec::new();
-        let symbol = order.symbol.clone();
-        let order_price = order.price.unwrap();
-        
-        let mut book = self.orderbooks.get_mut(&symbol).unwrap();
-        
-        let opposite_side = match order.side {
-            OrderSide::Buy => OrderSide::Sell,
-            OrderSide::Sell => OrderSide::Buy,
-        };
 
-        let levels: Vec<(Decimal, Vec<Uuid>)> = match opposite_side {
-            OrderSide::Buy => book.bids.iter().rev()
-                .filter(|(price, _)| **price >= order_price)
-                .map(|(price, level)| (*price, level.orders.clone()))
-                .collect(),
-            OrderSide::Sell => book.asks.iter()
-                .filter(|(price, _)| **price <= order_price)
-                .map(|(price, level)| (*price, level.orders.clone()))
-                .collect(),
-        };
+        // Each pass releases everything crossed by the current last price;
+        // stops crossed by those executions are picked up by the next pass.
+        while let Some(last_price) = self.orderbooks.get(symbol).and_then(|b| b.last_trade_price) {
+            self.update_trailing_stops(symbol);
+
+            let triggered = match self.stop_books.get_mut(symbol) {
+                Some(mut stops) => stops.take_triggered(last_price),
+                None => break,
+            };
 
-        for (price, order_ids) in levels {
-            if order.is_fully_filled() {
+            if triggered.is_empty() {
                 break;
             }
 
-            for order_id in order_ids {
-                if order.is_fully_filled() {
-                    break;
+            for order_id in triggered {
+                let Some((_, order)) = self.orders.remove(&order_id) else {
+                    continue;
+                };
+
+                self.publish(EngineEvent::StopTriggered {
+                    symbol: symbol.to_string(),
+                    order_id,
+                    stop_price: order.stop_price.unwrap_or(Decimal::ZERO),
+                    last_price,
+                    timestamp: Utc::now(),
+                });
+
+                match self.execute_order(order) {
+                    Ok(stop_trades) => trades.extend(stop_trades),
+                    Err(e) => warn!("Triggered stop order {} failed: {}", order_id, e),
                 }
+            }
+        }
 
-                if let Some(mut matching_order) = self.orders.get_mut(&order_id) {
-                    let trade_quantity = order.remaining_quantity().min(matching_order.remaining_quantity());
-                    
-                    let (buyer_id, seller_id) = match order.side {
-                        OrderSide::Buy => (order.id, matching_order.id),
-                        OrderSide::Sell => (matching_order.id, order.id),
-                    };
+        trades
+    }
+
+    fn trailing_stop_price(&self, order: &Order, trailing: TrailingStop) -> Option<Decimal> {
+        let book = self.orderbooks.get(&order.symbol)?;
+
+        let reference = match (trailing.reference, order.side) {
+            (TrailReference::LastTrade, _) => book.last_trade_price,
+            (TrailReference::BestPrice, OrderSide::Buy) => book.best_ask(),
+            (TrailReference::BestPrice, OrderSide::Sell) => book.best_bid(),
+        }?;
+
+        Some(trailing.stop_price(order.side, reference, self.tick_size(&book, reference)))
+    }
+
+    fn update_trailing_stops(&self, symbol: &str) {
+        let trailing_ids = match self.stop_books.get(symbol) {
+            Some(stops) if !stops.trailing.is_empty() => stops.trailing.clone(),
+            _ => return,
+        };
+
+        for order_id in trailing_ids {
+            let Some(order) = self.get_order(order_id) else {
+                continue;
+            };
+            let Some(trailing) = order.trailing_stop else {
+                continue;
+            };
+            let Some(stop_price) = self.trailing_stop_price(&order, trailing) else {
+                continue;
+            };
 
-                    let trade = Trade::new(
-                        symbol.clone(),
-                        buyer_id,
-                        seller_id,
-                        price,
-                        trade_quantity,
-                        order.side,
-                    );
+            // Re-key the parked order under its new stop price
+            let mut moved = order.clone();
+            if moved.trail_stop_to(stop_price) {
+                if let Some(mut stops) = self.stop_books.get_mut(symbol) {
+                    stops.remove_order(&order);
+                    stops.add_order(&moved);
+                    self.orders.insert(order_id, moved);
+                }
+            }
+        }
+    }
+
+    fn peg_price(&self, order: &Order) -> Option<Decimal> {
+        let peg = order.peg?;
+        let book = self.orderbooks.get(&order.symbol)?;
+        Self::pegged_price(&book, order.side, peg)
+    }
+
+    /// Where `peg` puts an order on `side`, rounded onto the book's grid
+    /// away from the opposite side.
+    fn pegged_price(book: &OrderBook, side: OrderSide, peg: Peg) -> Option<Decimal> {
+        let (reference_bid, reference_ask) = book.reference_prices();
+        let price = peg.price(side, reference_bid, reference_ask)?;
+        book.passive_price(side, price).ok().map(|price| book.price_value(price))
+    }
 
-                    order.fill(trade_quantity);
-                    matching_order.fill(trade_quantity);
+    /// Moves resting pegged orders to their current peg price. A repriced
+    /// order joins the back of its new level like a fresh order, but is kept
+    /// one tick behind the opposite touch so repricing never takes liquidity.
+    fn reprice_pegged_orders(&self, symbol: &str) {
+        let pegged = match self.orderbooks.get(symbol) {
+            Some(book) if !book.pegged.is_empty() && book.auction.is_none() => book.pegged.clone(),
+            _ => return,
+        };
+
+        // Clamping against the touch can depend on other pegs, so repeat
+        // until no price changes
+        for _ in 0..=pegged.len() {
+            let mut repriced = false;
+
+            for order_id in &pegged {
+                let Some(mut book) = self.orderbooks.get_mut(symbol) else {
+                    return;
+                };
+                let Some(mut order) = self.orders.get_mut(order_id) else {
+                    continue;
+                };
+                let Some(peg) = order.peg else {
+                    continue;
+                };
+                if !order.is_active() {
+                    continue;
+                }
 
-                    trades.push(trade);
+                let Some(mut price) = Self::pegged_price(&book, order.side, peg) else {
+                    continue;
+                };
 
-                    if matching_order.is_fully_filled() {
-                        book.remove_order(&matching_order);
+                // Hidden orders count here too, or the peg would cross them
+                let touch = book.front(order.side.opposite()).map(|(touch, _)| touch);
+                match order.side {
+                    OrderSide::Buy => {
+                        if let Some(ask) = touch.filter(|ask| price >= *ask) {
+                            price = ask - self.tick_size(&book, ask);
+                        }
                     }
+                    OrderSide::Sell => {
+                        if let Some(bid) = touch.filter(|bid| price <= *bid) {
+                            price = bid + self.tick_size(&book, bid);
+                        }
+                    }
+                }
+
+                if price <= Decimal::ZERO || order.price == Some(price) {
+                    continue;
+                }
+
+                let old_price = order.price.unwrap_or(Decimal::ZERO);
+                if let Err(e) = book.move_order(&order, price) {
+                    warn!("Pegged order {} keeps its price: {}", order_id, e);
+                    continue;
                 }
+                order.price = Some(price);
+                order.updated_at = Utc::now();
+                repriced = true;
+
+                self.publish(EngineEvent::PegRepriced {
+                    symbol: symbol.to_string(),
+                    order_id: *order_id,
+                    old_price,
+                    new_price: price,
+                    timestamp: order.updated_at,
+                });
+            }
+
+            if !repriced {
+                break;
             }
         }
+    }
 
-        Ok(trades)
+    fn publish(&self, event: EngineEvent) {
+        // The engine holds a receiver, so sending can never fail
+        let _ = self.events_tx.send(event);
+    }
+
+    /// Drains all events published since the previous call.
+    pub fn drain_events(&self) -> Vec<EngineEvent> {
+        self.events_rx.try_iter().collect()
     }
 
+    /// Cancels a working order. Cancelling a group leg applies the group's
+    /// rule, so an OCO sibling is cancelled with it and the children of a
+    /// partially filled parent are released.
     pub fn cancel_order(&self, order_id: Uuid) -> Result<(), String> {
-        if let Some(mut order) = self.orders.get_mut(&order_id) {
+        self.cancel_working(order_id)?;
+        self.reconcile_groups([order_id]);
+
+        Ok(())
+    }
+
+    fn cancel_working(&self, order_id: Uuid) -> Result<(), String> {
+        let symbol = self.close_resting(order_id, |order| {
             if order.status == OrderStatus::Filled {
                 return Err("Cannot cancel filled order".to_string());
             }
+            let session = self.session_state(&order.symbol);
+            if !session.accepts_cancels() {
+                return Err(format!("Cancels are not accepted for {} while {:?}", order.symbol, session));
+            }
 
-            let symbol = order.symbol.clone();
             order.cancel();
+            Ok(())
+        })?;
 
-            if let Some(mut book) = self.orderbooks.get_mut(&symbol) {
-                book.remove_order(&order);
+        self.reprice_pegged_orders(&symbol);
+        self.publish_indicative(&symbol);
+
+        Ok(())
+    }
+
+    /// Submits two or more orders as a one-cancels-other group. The first
+    /// fill or cancel on any leg cancels the others; if a leg trades on
+    /// entry, the legs after it are recorded as cancelled without being
+    /// submitted. If a leg is rejected while nothing has traded, the legs
+    /// already accepted are cancelled and the group is dropped.
+    pub fn submit_oco(&self, legs: Vec<Order>) -> Result<(Uuid, Vec<Trade>), String> {
+        if legs.len() < 2 {
+            return Err("An OCO group needs at least two orders".to_string());
+        }
+        for leg in &legs {
+            self.validate_order(leg)?;
+        }
+
+        let group = OrderGroup::new(GroupKind::Oco, legs.iter().map(|leg| leg.id).collect(), Vec::new());
+        let group_id = self.register_group(group);
+
+        let mut trades = Vec::new();
+        let mut accepted = Vec::new();
+        for mut leg in legs {
+            let resolved = self
+                .groups
+                .get(&group_id)
+                .is_some_and(|group| group.status != GroupStatus::Working);
+            if resolved {
+                leg.cancel();
+                self.orders.insert(leg.id, leg);
+                continue;
             }
 
-            Ok(())
-        } else {
-            Err("Order not found".to_string())
+            let leg_id = leg.id;
+            match self.submit_order(leg) {
+                Ok(leg_trades) => {
+                    trades.extend(leg_trades);
+                    accepted.push(leg_id);
+                }
+                Err(e) => {
+                    // Nothing has traded, or the group would have resolved
+                    self.group_legs.retain(|_, group| *group != group_id);
+                    self.groups.remove(&group_id);
+                    for order_id in accepted {
+                        if let Err(e) = self.cancel_working(order_id) {
+                            warn!("Could not cancel OCO leg {}: {}", order_id, e);
+                        }
+                    }
+                    return Err(e);
+                }
+            }
         }
+
+        Ok((group_id, trades))
     }
 
-    pub fn get_order(&self, order_id: Uuid) -> Option<Order> {
-        self.orders.get(&order_id).map(|o| o.clone())
+    /// Submits `parent` and holds `children` back until it is done trading.
+    /// They are released once the parent fills, or when it stops working
+    /// after a partial fill, and are dropped if it never trades.
+    pub fn submit_oto(&self, parent: Order, children: Vec<Order>) -> Result<(Uuid, Vec<Trade>), String> {
+        self.validate_order(&parent)?;
+        for child in &children {
+            self.validate_order(child)?;
+        }
+
+        let group = OrderGroup::new(GroupKind::Oto, vec![parent.id], children);
+        self.submit_parent(group, parent)
     }
 
-    pub fn get_orderbook(&self, symbol: &str) -> Option<OrderBook> {
-        self.orderbooks.get(symbol).map(|b| b.clone())
+    /// Submits an entry with a take-profit limit and a stop-loss held back
+    /// until the entry is done trading. Both are sized to the entry's filled
+    /// quantity when released and then behave as an OCO pair.
+    pub fn submit_bracket(
+        &self,
+        entry: Order,
+        take_profit: Order,
+        stop_loss: Order,
+    ) -> Result<(Uuid, Vec<Trade>), String> {
+        self.validate_order(&entry)?;
+        self.validate_order(&take_profit)?;
+        self.validate_order(&stop_loss)?;
+
+        let exit_side = entry.side.opposite();
+        if take_profit.side != exit_side || stop_loss.side != exit_side {
+            return Err("Bracket exits must be on the opposite side of the entry".to_string());
+        }
+        if take_profit.symbol != entry.symbol || stop_loss.symbol != entry.symbol {
+            return Err("Bracket orders must share a symbol".to_string());
+        }
+        if take_profit.order_type != OrderType::Limit || !stop_loss.is_stop() {
+            return Err("A bracket needs a limit take-profit and a stop-loss".to_string());
+        }
+
+        let group = OrderGroup::new(GroupKind::Bracket, vec![entry.id], vec![take_profit, stop_loss]);
+        self.submit_parent(group, entry)
     }
-}
 
-impl Default for MatchingEngine {
-    fn default() -> Self {
-        Self::new()
+    fn submit_parent(&self, group: OrderGroup, parent: Order) -> Result<(Uuid, Vec<Trade>), String> {
+        let group_id = self.register_group(group);
+
+        match self.submit_order(parent) {
+            Ok(trades) => Ok((group_id, trades)),
+            Err(e) => {
+                self.group_legs.retain(|_, group| *group != group_id);
+                self.groups.remove(&group_id);
+                Err(e)
+            }
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rust_decimal_macros::dec;
+    fn register_group(&self, group: OrderGroup) -> Uuid {
+        let group_id = group.id;
+        for order_id in group.legs.iter().chain(group.pending.iter().map(|child| &child.id)) {
+            self.group_legs.insert(*order_id, group_id);
+        }
+        self.groups.insert(group_id, group);
+        group_id
+    }
 
-    #[test]
-    fn test_limit_order_matching() {
-        let engine = MatchingEngine::new();
+    pub fn get_group(&self, group_id: Uuid) -> Option<OrderGroup> {
+        self.groups.get(&group_id).map(|g| g.clone())
+    }
 
-        let sell_order = Order::new(
-            "AAPL".to_string(),
-            OrderSide::Sell,
-            OrderType::Limit,
-            dec!(100),
-            Some(dec!(150.00)),
-            None,
-            "seller".to_string(),
-        );
+    fn traded_order_ids(trades: &[Trade]) -> impl Iterator<Item = Uuid> + '_ {
+        trades
+            .iter()
+            .flat_map(|trade| [trade.buyer_order_id, trade.seller_order_id])
+    }
 
-        let buy_order = Order::new(
-            "AAPL".to_string(),
-            OrderSide::Buy,
-            OrderType::Limit,
-            dec!(100),
-            Some(dec!(150.00)),
-            None,
-            "buyer".to_string(),
-        );
+    /// Brings every group with a leg among `order_ids` in line with its
+    /// rule, and returns trades from any children that are released.
+    fn reconcile_groups(&self, order_ids: impl IntoIterator<Item = Uuid>) -> Vec<Trade> {
+        let mut group_ids: Vec<Uuid> = order_ids
+            .into_iter()
+            .filter_map(|order_id| self.group_legs.get(&order_id).map(|group| *group))
+            .collect();
+        group_ids.sort();
+        group_ids.dedup();
 
-        engine.submit_order(sell_order).unwrap();
-        let trades = engine.submit_order(buy_order).unwrap();
+        let mut trades = Vec::new();
+        for group_id in group_ids {
+            let actions = {
+                let Some(mut group) = self.groups.get_mut(&group_id) else {
+                    continue;
+                };
+                let legs: Vec<Order> = group.legs.iter().filter_map(|id| self.get_order(*id)).collect();
+                group.update(&legs)
+            };
 
-        assert_eq!(trades.len(), 1);
-        assert_eq!(trades[0].quantity, dec!(100));
-        assert_eq!(trades[0].price, dec!(150.00));
+            for order_id in actions.cancel {
+                if let Err(e) = self.cancel_working(order_id) {
+                    warn!("Group {} could not cancel {}: {}", group_id, order_id, e);
+                }
+            }
+            for (order_id, quantity) in actions.reduce {
+                if let Err(e) = self.amend_order(order_id, Some(quantity), None) {
+                    warn!("Group {} could not reduce {}: {}", group_id, order_id, e);
+                }
+            }
+            for child in actions.release {
+                match self.submit_order(child) {
+                    Ok(child_trades) => trades.extend(child_trades),
+                    Err(e) => warn!("Group {} could not release a child: {}", group_id, e),
+                }
+            }
+        }
+
+        trades
     }
 
-    #[test]
-    fn test_partial_fill() {
-        let engine = MatchingEngine::new();
+    /// Cancels every working order matching `filter` and returns their ids.
+    /// Each affected symbol's book and stop book are locked for the whole
+    /// sweep, so no match can interleave with it. Symbols whose session
+    /// does not accept cancels are left alone.
+    pub fn mass_cancel(&self, filter: &MassCancelFilter) -> Vec<Uuid> {
+        self.cancel_where(filter, |_| true)
+    }
 
-        let sell_order = Order::new(
-            "AAPL".to_string(),
-            OrderSide::Sell,
-            OrderType::Limit,
-            dec!(50),
-            Some(dec!(150.00)),
-            None,
-            "seller".to_string(),
-        );
+    fn cancel_where(&self, filter: &MassCancelFilter, include: impl Fn(&Order) -> bool) -> Vec<Uuid> {
+        let mut by_symbol: BTreeMap<String, Vec<(DateTime<Utc>, Uuid)>> = BTreeMap::new();
+        for order in self.orders.iter().filter(|order| include(order) && filter.matches(order)) {
+            by_symbol
+                .entry(order.symbol.clone())
+                .or_default()
+                .push((order.timestamp, order.id));
+        }
 
-        let buy_order = Order::new(
-            "AAPL".to_string(),
-            OrderSide::Buy,
-            OrderType::Limit,
-            dec!(100),
-            Some(dec!(150.00)),
-            None,
-            "buyer".to_string(),
-        );
+        let mut cancelled = Vec::new();
+        for (symbol, mut candidates) in by_symbol {
+            if !self.session_state(&symbol).accepts_cancels() {
+                continue;
+            }
+            candidates.sort();
 
-        engine.submit_order(sell_order).unwrap();
-        let trades = engine.submit_order(buy_order.clone()).unwrap();
+            {
+                let (Some(mut book), Some(mut stops)) =
+                    (self.orderbooks.get_mut(&symbol), self.stop_books.get_mut(&symbol))
+                else {
+                    continue;
+                };
 
-        assert_eq!(trades.len(), 1);
-        assert_eq!(trades[0].quantity, dec!(50));
+                for (_, order_id) in candidates {
+                    let Some(mut order) = self.orders.get_mut(&order_id) else {
+                        continue;
+                    };
+                    // The order may have traded since it was selected
+                    if !filter.matches(&order) {
+                        continue;
+                    }
 
-        let stored_order = engine.get_order(buy_order.id).unwrap();
-        assert_eq!(stored_order.filled_quantity, dec!(50));
-        assert_eq!(stored_order.status, OrderStatus::PartiallyFilled);
-    }
+                    if !(order.is_stop() && stops.remove_order(&order)) {
+                        book.remove_order(&order);
+                    }
+                    order.cancel();
+                    cancelled.push(order_id);
+                }
+            }
 
-    #[test]
-    fn test_order_cancellation() {
-        let engine = MatchingEngine::new();
+            self.reprice_pegged_orders(&symbol);
+            self.publish_indicative(&symbol);
+        }
 
-        let order = Order::new(
-            "AAPL".to_string(),
-            OrderSide::Buy,
-            OrderType::Limit,
-            dec!(100),
-            Some(dec!(150.00)),
-            None,
-            "buyer".to_string(),
-        );
+        self.reconcile_groups(cancelled.iter().copied());
+        cancelled
+    }
 
-        let order_id = order.id;
+    /// Marks `session_id` as a client session of `user_id` whose working
+    /// orders are cancelled if the session disconnects.
+    pub fn enable_cancel_on_disconnect(&self, session_id: &str, user_id: &str) {
+        self.cancel_on_disconnect
+            .insert(session_id.to_string(), user_id.to_string());
+    }
+
+    /// Ends a client session. If it registered for cancel-on-disconnect,
+    /// every working order of its user is cancelled and the ids returned.
+    pub fn disconnect(&self, session_id: &str) -> Vec<Uuid> {
+        match self.cancel_on_disconnect.remove(session_id) {
+            Some((_, user_id)) => self.mass_cancel(&MassCancelFilter::default().with_user(&user_id)),
+            None => Vec::new(),
+        }
+    }
+
+    /// Replaces `user_id`'s two-sided quote on each entry's symbol and
+    /// acknowledges every entry. Each replace is atomic: the old bid and ask
+    /// are pulled and the new ones placed under one book lock, and an entry
+    /// that cannot be placed in full leaves the old quote working.
+    pub fn mass_quote(&self, user_id: &str, entries: Vec<QuoteEntry>) -> Vec<QuoteAck> {
+        entries
+            .into_iter()
+            .map(|entry| {
+                let quote_id = Uuid::new_v4();
+                let symbol = entry.symbol.clone();
+                let (status, trades) = match self.replace_quote(quote_id, user_id, &entry) {
+                    Ok(trades) => (QuoteStatus::Accepted, trades),
+                    Err(e) => (QuoteStatus::Rejected(e), Vec::new()),
+                };
+
+                QuoteAck {
+                    quote_id,
+                    symbol,
+                    status,
+                    trades,
+                }
+            })
+            .collect()
+    }
+
+    fn replace_quote(&self, quote_id: Uuid, user_id: &str, entry: &QuoteEntry) -> Result<Vec<Trade>, String> {
+        if let (Some((bid, _)), Some((ask, _))) = (entry.bid, entry.ask) {
+            if bid >= ask {
+                return Err(format!("Quote bid {} must be below ask {}", bid, ask));
+            }
+        }
+
+        let symbol = entry.symbol.clone();
+        let session = self.session_state(&symbol);
+        if !session.accepts_orders() {
+            return Err(format!("Orders are not accepted for {} while {:?}", symbol, session));
+        }
+
+        let mut orders = entry.orders(user_id);
+        for order in &orders {
+            self.validate_order(order)?;
+        }
+
+        let key = (user_id.to_string(), symbol.clone());
+        let mut book = self
+            .orderbooks
+            .get_mut(&symbol)
+            .ok_or_else(|| format!("Unknown symbol {}", symbol))?;
+        let previous: Vec<Uuid> = self
+            .quotes
+            .get(&key)
+            .map(|quote| quote.order_ids().collect())
+            .unwrap_or_default();
+
+        // The new bid only takes from asks and the new ask only from bids,
+        // so both can be planned against the book before either is committed
+        let mut plans = Vec::new();
+        for order in &mut orders {
+            plans.push(self.plan_order(&book, order, &previous)?);
+        }
+
+        for order_id in &previous {
+            if let Some(mut order) = self.orders.get_mut(order_id).filter(|order| order.is_active()) {
+                book.remove_order(&order);
+                order.cancel();
+            }
+        }
+
+        let mut trades = Vec::new();
+        let mut breaches = Vec::new();
+        let mut quote = MakerQuote {
+            quote_id,
+            user_id: user_id.to_string(),
+            symbol: symbol.clone(),
+            bid_order_id: None,
+            ask_order_id: None,
+            timestamp: Utc::now(),
+        };
+
+        for (mut order, plan) in orders.into_iter().zip(plans) {
+            trades.extend(self.commit_order(&mut book, &mut order, &plan));
+            match order.side {
+                OrderSide::Buy => quote.bid_order_id = Some(order.id),
+                OrderSide::Sell => quote.ask_order_id = Some(order.id),
+            }
+            if let Some(breach) = plan.breach {
+                breaches.push((order.id, breach));
+            }
+            self.orders.insert(order.id, order);
+        }
+
+        self.quotes.insert(key, quote);
+        drop(book);
+
+        for (order_id, breach) in breaches {
+            self.volatility_halt(&symbol, order_id, breach);
+        }
+
+        trades.extend(self.release_stop_orders(&symbol));
+        self.reprice_pegged_orders(&symbol);
+        self.publish_indicative(&symbol);
+
+        let touched = Self::traded_order_ids(&trades).chain(previous).collect::<Vec<_>>();
+        trades.extend(self.reconcile_groups(touched));
+
+        Ok(trades)
+    }
+
+    pub fn get_quote(&self, user_id: &str, symbol: &str) -> Option<MakerQuote> {
+        self.quotes
+            .get(&(user_id.to_string(), symbol.to_string()))
+            .map(|quote| quote.clone())
+    }
+
+    /// Pulls the working quote orders matching `filter` and returns their
+    /// ids. Quotes left with no working side are dropped.
+    pub fn mass_cancel_quotes(&self, filter: &MassCancelFilter) -> Vec<Uuid> {
+        let quote_orders: Vec<Uuid> = self
+            .quotes
+            .iter()
+            .flat_map(|quote| quote.order_ids().collect::<Vec<_>>())
+            .collect();
+
+        let cancelled = self.cancel_where(filter, |order| quote_orders.contains(&order.id));
+        self.quotes.retain(|_, quote| {
+            quote
+                .order_ids()
+                .any(|order_id| self.orders.get(&order_id).is_some_and(|order| order.is_active()))
+        });
+
+        cancelled
+    }
+
+    /// Changes the total quantity and/or limit price of a working order.
+    ///
+    /// Reducing the quantity keeps queue priority. Any price change or
+    /// quantity increase re-enters the order at the back of the queue, and a
+    /// new price that crosses the book trades immediately. An amend that is
+    /// rejected leaves the order as it was.
+    pub fn amend_order(
+        &self,
+        order_id: Uuid,
+        quantity: Option<Decimal>,
+        price: Option<Decimal>,
+    ) -> Result<Vec<Trade>, String> {
+        self.swap_working(order_id, |original| {
+            if !original.is_active() {
+                return Err("Only working orders can be amended".to_string());
+            }
+            let session = self.session_state(&original.symbol);
+            if !session.accepts_orders() {
+                return Err(format!("Amends are not accepted for {} while {:?}", original.symbol, session));
+            }
+
+            let mut amended = original.clone();
+            if let Some(quantity) = quantity {
+                if quantity <= amended.filled_quantity {
+                    return Err("Amended quantity must exceed the filled quantity".to_string());
+                }
+                amended.quantity = quantity;
+            }
+            if let Some(price) = price {
+                if amended.peg.is_some() {
+                    return Err("Pegged order prices are managed by the engine".to_string());
+                }
+                amended.price = Some(price);
+            }
+            self.validate_order(&amended)?;
+            amended.updated_at = Utc::now();
+
+            Ok(amended)
+        })
+    }
+
+    /// Cancels a working order and submits `replacement` in its place. The
+    /// replacement keeps the symbol and side, carries its own quantity and
+    /// trades immediately if it crosses the book. Both orders record the link.
+    /// A replacement that is rejected leaves the original working.
+    pub fn replace_order(&self, order_id: Uuid, mut replacement: Order) -> Result<Vec<Trade>, String> {
+        self.validate_order(&replacement)?;
+        if self.orders.contains_key(&replacement.id) {
+            return Err("Replacement must be a new order".to_string());
+        }
+        replacement.replaces = Some(order_id);
+        self.prepare_entry(&mut replacement)?;
+
+        self.swap_working(order_id, |original| {
+            if !original.is_active() {
+                return Err("Only working orders can be replaced".to_string());
+            }
+            if original.symbol != replacement.symbol || original.side != replacement.side {
+                return Err("Replacement must keep the symbol and side".to_string());
+            }
+            let session = self.session_state(&original.symbol);
+            if !session.accepts_orders() {
+                return Err(format!("Replaces are not accepted for {} while {:?}", original.symbol, session));
+            }
+
+            Ok(replacement)
+        })
+    }
+
+    /// Swaps the working order `order_id` for the successor `change` derives
+    /// from it and returns the trades the successor makes. The book and stop
+    /// book stay locked from reading the original to committing the
+    /// successor, so nothing trades against the original in between, and the
+    /// original is only pulled once the successor has been planned: a change
+    /// that is rejected leaves it working.
+    ///
+    /// A successor with the original's id takes its place, keeping its queue
+    /// position when the price is unchanged and the quantity not increased,
+    /// and staying parked if the original was a parked stop. One with a new
+    /// id marks the original as replaced by it.
+    fn swap_working(
+        &self,
+        order_id: Uuid,
+        change: impl FnOnce(&Order) -> Result<Order, String>,
+    ) -> Result<Vec<Trade>, String> {
+        let symbol = self
+            .orders
+            .get(&order_id)
+            .map(|order| order.symbol.clone())
+            .ok_or("Order not found")?;
+
+        let (successor_id, mut trades, breach) = {
+            let (Some(mut book), Some(mut stops)) =
+                (self.orderbooks.get_mut(&symbol), self.stop_books.get_mut(&symbol))
+            else {
+                return Err(format!("Unknown symbol {}", symbol));
+            };
+            let mut original = self.get_order(order_id).ok_or("Order not found")?;
+            let mut successor = change(&original)?;
+            let successor_id = successor.id;
+            let amends = successor_id == order_id;
+            let parked = original.is_stop() && stops.contains(&original);
+
+            if amends && successor.price == original.price && successor.quantity <= original.quantity {
+                book.reduce_order(&successor)?;
+                self.orders.insert(order_id, successor);
+                (successor_id, Vec::new(), None)
+            } else {
+                let parks = if amends { parked } else { successor.is_stop() };
+                let plan = if parks { None } else { Some(self.plan_order(&book, &mut successor, &[order_id])?) };
+
+                if !(parked && stops.remove_order(&original)) {
+                    book.remove_order(&original);
+                }
+                if !amends {
+                    original.replace(successor_id);
+                    self.orders.insert(order_id, original);
+                }
+
+                let trades = match &plan {
+                    Some(plan) => self.commit_order(&mut book, &mut successor, plan),
+                    None => {
+                        stops.add_order(&successor);
+                        Vec::new()
+                    }
+                };
+                self.orders.insert(successor_id, successor);
+                (successor_id, trades, plan.and_then(|plan| plan.breach))
+            }
+        };
+
+        if let Some(breach) = breach {
+            self.volatility_halt(&symbol, successor_id, breach);
+        }
+
+        trades.extend(self.release_stop_orders(&symbol));
+        self.reprice_pegged_orders(&symbol);
+        self.publish_indicative(&symbol);
+
+        let touched = Self::traded_order_ids(&trades).chain([successor_id]).collect::<Vec<_>>();
+        trades.extend(self.reconcile_groups(touched));
+
+        Ok(trades)
+    }
+
+    /// Expires DAY orders entered on an earlier trading date than `now` and
+    /// GTD orders whose expiry has passed. Intended to be run periodically;
+    /// a sweep during the day only ever expires GTD orders.
+    pub fn expire_orders(&self, now: DateTime<Utc>) -> Vec<Uuid> {
+        let mut expiring: Vec<(DateTime<Utc>, Uuid)> = self
+            .orders
+            .iter()
+            .filter(|o| o.is_active() && o.is_expired_at(now))
+            .map(|o| (o.timestamp, o.id))
+            .collect();
+        expiring.sort();
+
+        let mut expired = Vec::with_capacity(expiring.len());
+        for (_, order_id) in expiring {
+            let closed = self.close_resting(order_id, |order| {
+                if !order.is_active() {
+                    return Err("Order is no longer working".to_string());
+                }
+                order.expire();
+                Ok(())
+            });
+            let Ok(symbol) = closed else {
+                continue;
+            };
+
+            self.publish(EngineEvent::OrderExpired {
+                symbol: symbol.clone(),
+                order_id,
+                timestamp: now,
+            });
+            expired.push((symbol, order_id));
+        }
+
+        let symbols: BTreeSet<&String> = expired.iter().map(|(symbol, _)| symbol).collect();
+        for symbol in symbols {
+            self.reprice_pegged_orders(symbol);
+            self.publish_indicative(symbol);
+        }
+
+        let expired: Vec<Uuid> = expired.into_iter().map(|(_, order_id)| order_id).collect();
+        self.reconcile_groups(expired.iter().copied());
+        expired
+    }
+
+    /// Applies `close` to a working order and takes it off its book or stop
+    /// book, returning its symbol. Nothing is removed if `close` fails. The
+    /// book, the stop book and then the order are locked, the same order
+    /// matching uses, so this is safe to run alongside it.
+    fn close_resting(
+        &self,
+        order_id: Uuid,
+        close: impl FnOnce(&mut Order) -> Result<(), String>,
+    ) -> Result<String, String> {
+        let symbol = self
+            .orders
+            .get(&order_id)
+            .map(|order| order.symbol.clone())
+            .ok_or("Order not found")?;
+
+        let mut book = self.orderbooks.get_mut(&symbol);
+        let mut stops = self.stop_books.get_mut(&symbol);
+        let mut order = self.orders.get_mut(&order_id).ok_or("Order not found")?;
+        close(&mut order)?;
+
+        let parked = order.is_stop() && stops.as_mut().is_some_and(|stops| stops.remove_order(&order));
+        if !parked {
+            if let Some(book) = book.as_mut() {
+                book.remove_order(&order);
+            }
+        }
+
+        Ok(symbol)
+    }
+
+    pub fn get_order(&self, order_id: Uuid) -> Option<Order> {
+        self.orders.get(&order_id).map(|o| o.clone())
+    }
+
+    /// Adds or updates the reference data for a symbol. Only registered
+    /// symbols can be traded.
+    pub fn register_instrument(&self, instrument: Instrument) {
+        let symbol = instrument.symbol.clone();
+
+        {
+            let mut book = self
+                .orderbooks
+                .entry(symbol.clone())
+                .or_insert_with(|| OrderBook::new(symbol.clone()));
+            book.tick_size = instrument.min_tick_size();
+
+            // Orders already resting keep their prices and sizes, so the book
+            // moves to a grid that fits both the old and the new reference data
+            let (mut price_unit, mut lot_size) = (instrument.price_unit(), instrument.quantity_unit());
+            if !book.is_empty() {
+                price_unit = common_unit(price_unit, book.price_unit()).unwrap_or(price_unit);
+                lot_size = common_unit(lot_size, book.lot_size()).unwrap_or(lot_size);
+            }
+            if let Err(e) = book.rescale(price_unit, lot_size) {
+                warn!("{} keeps its previous price and lot grid: {}", symbol, e);
+            }
+        }
+        self.stop_books
+            .entry(symbol.clone())
+            .or_insert_with(|| StopBook::new(symbol.clone()));
+
+        self.instruments.insert(symbol, instrument);
+    }
+
+    pub fn get_instrument(&self, symbol: &str) -> Option<Instrument> {
+        self.instruments.get(symbol).map(|i| i.clone())
+    }
+
+    fn validate_order(&self, order: &Order) -> Result<(), String> {
+        let instrument = self
+            .instruments
+            .get(&order.symbol)
+            .ok_or_else(|| format!("Unknown symbol {}", order.symbol))?;
+
+        order.validate_for(&instrument)
+    }
+
+    /// Tick size at `price`, from the instrument's tick table.
+    fn tick_size(&self, book: &OrderBook, price: Decimal) -> Decimal {
+        self.instruments
+            .get(&book.symbol)
+            .map_or(book.tick_size, |instrument| instrument.tick_size_at(price))
+    }
+
+    /// Chooses how fills are allocated within a price level for `symbol`.
+    /// Symbols without an explicit choice use price-time priority.
+    pub fn set_matching_algorithm(&self, symbol: &str, algorithm: impl MatchingAlgorithm + 'static) {
+        self.algorithms.insert(symbol.to_string(), Arc::new(algorithm));
+    }
+
+    /// Puts `symbol` into a call auction. Orders entered from now on rest
+    /// without matching until `uncross_auction` is called.
+    pub fn start_auction(&self, symbol: &str, kind: AuctionKind) {
+        self.orderbooks
+            .entry(symbol.to_string())
+            .or_insert_with(|| OrderBook::new(symbol.to_string()))
+            .auction = Some(kind);
+        self.stop_books
+            .entry(symbol.to_string())
+            .or_insert_with(|| StopBook::new(symbol.to_string()));
+
+        self.publish_indicative(symbol);
+    }
+
+    /// The price and volume the auction for `symbol` would uncross at if it
+    /// ended now.
+    pub fn indicative_uncross(&self, symbol: &str) -> Option<Uncross> {
+        let book = self.orderbooks.get(symbol)?;
+        book.auction?;
+
+        self.auction_match(&book).map(|(uncross, _)| uncross)
+    }
+
+    fn publish_indicative(&self, symbol: &str) {
+        let Some(kind) = self.orderbooks.get(symbol).and_then(|book| book.auction) else {
+            return;
+        };
+
+        self.publish(EngineEvent::AuctionIndicative {
+            symbol: symbol.to_string(),
+            kind,
+            uncross: self.indicative_uncross(symbol),
+            timestamp: Utc::now(),
+        });
+    }
+
+    /// Total remaining quantity per limit price on each side, including
+    /// iceberg reserves, which take full part in auctions. Orders in
+    /// `excluded` are left out.
+    fn auction_interest(
+        &self,
+        book: &OrderBook,
+        excluded: &HashSet<Uuid>,
+    ) -> (BTreeMap<Decimal, Decimal>, BTreeMap<Decimal, Decimal>) {
+        let interest = |levels: &BTreeMap<Price, PriceLevel>| {
+            levels
+                .iter()
+                .filter_map(|(price, level)| {
+                    let quantity: Qty = book
+                        .queue(level)
+                        .filter(|entry| !excluded.contains(&entry.order_id))
+                        .map(|entry| entry.remaining)
+                        .sum();
+                    (quantity > Qty::ZERO).then(|| (book.price_value(*price), book.qty_value(quantity)))
+                })
+                .collect()
+        };
+
+        (interest(&book.bids), interest(&book.asks))
+    }
+
+    /// Ends the auction for `symbol`. Every order crossing the uncrossing
+    /// price trades at that single price in price-time priority, and the
+    /// book returns to continuous matching. Returns the auction trades,
+    /// followed by any trades from stops they release, and the summary that
+    /// is also published as an event.
+    pub fn uncross_auction(&self, symbol: &str) -> Result<(Vec<Trade>, AuctionSummary), String> {
+        let mut book = self.orderbooks.get_mut(symbol).ok_or("Unknown symbol")?;
+        let kind = book.auction.ok_or("No auction in progress")?;
+
+        let matched = self.auction_match(&book);
+        let uncross = matched.as_ref().map(|(uncross, _)| *uncross);
+
+        let mut trades = Vec::new();
+        if let Some((uncross, fills)) = matched {
+            for fill in fills {
+                let quantity = book.qty_value(fill.quantity);
+                trades.push(Trade::new(
+                    symbol.to_string(),
+                    fill.buy_order_id,
+                    fill.sell_order_id,
+                    uncross.price,
+                    quantity,
+                    fill.aggressor,
+                ));
+
+                for order_id in [fill.buy_order_id, fill.sell_order_id] {
+                    if let Some(mut order) = self.orders.get_mut(&order_id) {
+                        order.fill(quantity);
+                        book.fill_order(&order, fill.quantity);
+                    }
+                }
+            }
+
+            book.last_trade_price = Some(uncross.price);
+
+            // The auction price becomes the static band reference
+            if let Some(mut bands) = self.price_bands.get_mut(symbol) {
+                bands.reference_price = Some(uncross.price);
+            }
+        }
+
+        book.auction = None;
+        drop(book);
+
+        let summary = AuctionSummary {
+            symbol: symbol.to_string(),
+            kind,
+            uncross,
+            trade_count: trades.len(),
+            timestamp: Utc::now(),
+        };
+        self.publish(EngineEvent::AuctionUncrossed {
+            summary: summary.clone(),
+        });
+
+        trades.extend(self.release_stop_orders(symbol));
+        self.reprice_pegged_orders(symbol);
+
+        let touched = Self::traded_order_ids(&trades).collect::<Vec<_>>();
+        trades.extend(self.reconcile_groups(touched));
+
+        Ok((trades, summary))
+    }
+
+    /// Works out how the auction on `book` would uncross: the price and
+    /// volume, and the fills pairing buys with sells in priority order. An
+    /// all-or-none or minimum quantity order that would trade less than it
+    /// accepts is left out, and the auction is worked out again without it.
+    fn auction_match(&self, book: &OrderBook) -> Option<(Uncross, Vec<AuctionFill>)> {
+        let mut excluded = HashSet::new();
+
+        loop {
+            let (bids, asks) = self.auction_interest(book, &excluded);
+            let uncross = Uncross::compute(&bids, &asks, book.last_trade_price)?;
+
+            let mut buys = self.auction_queue(book, OrderSide::Buy, uncross.price, &excluded);
+            let mut sells = self.auction_queue(book, OrderSide::Sell, uncross.price, &excluded);
+            let minimums: Vec<(Uuid, Qty)> = buys
+                .iter()
+                .chain(&sells)
+                .filter(|order| order.min_execution > Qty::ZERO)
+                .map(|order| (order.order_id, order.min_execution))
+                .collect();
+            // The volume is summed from resting quantities, so it is whole lots
+            let mut volume = book.to_qty(uncross.volume, Rounding::Down).ok()?;
+
+            let mut fills = Vec::new();
+            let mut filled: HashMap<Uuid, Qty> = HashMap::new();
+            while volume > Qty::ZERO {
+                let (Some(buy), Some(sell)) = (buys.front_mut(), sells.front_mut()) else {
+                    break;
+                };
+
+                let quantity = volume.min(buy.remaining).min(sell.remaining);
+                fills.push(AuctionFill {
+                    buy_order_id: buy.order_id,
+                    sell_order_id: sell.order_id,
+                    quantity,
+                    // The later of the two orders counts as the aggressor
+                    aggressor: if buy.timestamp >= sell.timestamp { OrderSide::Buy } else { OrderSide::Sell },
+                });
+                for order_id in [buy.order_id, sell.order_id] {
+                    *filled.entry(order_id).or_insert(Qty::ZERO) += quantity;
+                }
+
+                volume -= quantity;
+                buy.remaining -= quantity;
+                sell.remaining -= quantity;
+                if buy.remaining <= Qty::ZERO {
+                    buys.pop_front();
+                }
+                if sell.remaining <= Qty::ZERO {
+                    sells.pop_front();
+                }
+            }
+
+            let short = minimums.into_iter().filter(|(order_id, min)| {
+                filled.get(order_id).is_some_and(|filled| *filled > Qty::ZERO && filled < min)
+            });
+            let before = excluded.len();
+            excluded.extend(short.map(|(order_id, _)| order_id));
+            if excluded.len() == before {
+                return Some((uncross, fills));
+            }
+        }
+    }
+
+    /// Orders on `side` willing to trade at `price` and not in `excluded`,
+    /// best price first and in time priority within a level.
+    fn auction_queue(
+        &self,
+        book: &OrderBook,
+        side: OrderSide,
+        price: Decimal,
+        excluded: &HashSet<Uuid>,
+    ) -> VecDeque<AuctionOrder> {
+        // Bids at or above the price and asks at or below it, whether or not
+        // the price itself is on the grid
+        let levels: Box<dyn Iterator<Item = &PriceLevel>> = match side {
+            OrderSide::Buy => match book.to_price(price, Rounding::Up) {
+                Ok(price) => Box::new(book.bids.range(price..).rev().map(|(_, level)| level)),
+                Err(_) => Box::new(std::iter::empty()),
+            },
+            OrderSide::Sell => match book.to_price(price, Rounding::Down) {
+                Ok(price) => Box::new(book.asks.range(..=price).map(|(_, level)| level)),
+                Err(_) => Box::new(std::iter::empty()),
+            },
+        };
+
+        levels
+            .flat_map(|level| book.queue(level))
+            .filter(|entry| !excluded.contains(&entry.order_id))
+            .filter_map(|entry| {
+                let order = self.orders.get(&entry.order_id)?;
+                Some(AuctionOrder {
+                    order_id: order.id,
+                    remaining: entry.remaining,
+                    min_execution: book.to_qty(order.min_execution(), Rounding::Up).unwrap_or(Qty::new(i64::MAX)),
+                    timestamp: order.timestamp,
+                })
+            })
+            .collect()
+    }
+
+    pub fn session_state(&self, symbol: &str) -> SessionState {
+        self.sessions.get(symbol).map(|state| *state).unwrap_or_default()
+    }
+
+    /// Moves `symbol` to a new trading phase. Entering pre-open or pre-close
+    /// starts the matching call auction; leaving it for continuous trading
+    /// or the close uncrosses it, and the auction trades are returned.
+    pub fn set_session_state(&self, symbol: &str, state: SessionState) -> Result<Vec<Trade>, String> {
+        let from = self.session_state(symbol);
+        if !from.can_transition_to(state) {
+            return Err(format!("Cannot move {} from {:?} to {:?}", symbol, from, state));
+        }
+
+        self.sessions.insert(symbol.to_string(), state);
+        self.publish(EngineEvent::SessionChanged {
+            symbol: symbol.to_string(),
+            from,
+            to: state,
+            timestamp: Utc::now(),
+        });
+
+        let in_auction = self
+            .orderbooks
+            .get(symbol)
+            .is_some_and(|book| book.auction.is_some());
+
+        match state {
+            SessionState::PreOpen => self.start_auction(symbol, AuctionKind::Opening),
+            SessionState::PreClose => self.start_auction(symbol, AuctionKind::Closing),
+            SessionState::Continuous | SessionState::Closed if in_auction => {
+                return self.uncross_auction(symbol).map(|(trades, _)| trades);
+            }
+            _ => {}
+        }
+
+        Ok(Vec::new())
+    }
+
+    /// Configures the price bands checked on every continuous execution for
+    /// `symbol`.
+    pub fn set_price_bands(&self, symbol: &str, bands: PriceBands) {
+        self.price_bands.insert(symbol.to_string(), bands);
+    }
+
+    /// Halts `symbol` after an attempted trade outside its price band and
+    /// moves it straight into a re-opening auction call, which ends like any
+    /// pre-open with a transition to continuous trading.
+    fn volatility_halt(&self, symbol: &str, order_id: Uuid, breach: BandBreach) {
+        self.publish(EngineEvent::VolatilityHalt {
+            symbol: symbol.to_string(),
+            order_id,
+            breach,
+            timestamp: Utc::now(),
+        });
+
+        for state in [SessionState::Halted, SessionState::PreOpen] {
+            if let Err(e) = self.set_session_state(symbol, state) {
+                warn!("Volatility halt: {}", e);
+            }
+        }
+    }
+
+    /// Schedules session changes for `symbol` from a daily timetable. The
+    /// timetable is applied by `run_timetables`.
+    pub fn set_timetable(&self, symbol: &str, timetable: Timetable) {
+        self.timetables.insert(symbol.to_string(), (timetable, None));
+    }
+
+    /// Applies every timetable at `now`. A symbol only changes phase when
+    /// its scheduled state differs from the one applied last, so manual
+    /// transitions such as a halt hold until the next scheduled change.
+    /// Intended to be run periodically, like `expire_orders`.
+    pub fn run_timetables(&self, now: DateTime<Utc>) -> Vec<Trade> {
+        let due: Vec<(String, SessionState)> = self
+            .timetables
+            .iter_mut()
+            .filter_map(|mut entry| {
+                let scheduled = entry.0.state_at(now.time())?;
+                if entry.1 == Some(scheduled) {
+                    return None;
+                }
+                entry.1 = Some(scheduled);
+                Some((entry.key().clone(), scheduled))
+            })
+            .collect();
+
+        let mut trades = Vec::new();
+        for (symbol, state) in due {
+            if self.session_state(&symbol) == state {
+                continue;
+            }
+            match self.set_session_state(&symbol, state) {
+                Ok(auction_trades) => trades.extend(auction_trades),
+                Err(e) => warn!("Skipping scheduled session change: {}", e),
+            }
+        }
+
+        trades
+    }
+
+    pub fn get_orderbook(&self, symbol: &str) -> Option<OrderBook> {
+        self.orderbooks.get(symbol).map(|b| b.clone())
+    }
+
+    /// Takes everything the engine holds for `symbol` out of it, so the
+    /// symbol can be handed to another engine with `attach_symbol`. Working
+    /// orders go with the book, along with their order groups and the
+    /// cancel-on-disconnect sessions of their users. A symbol cannot be
+    /// detached during an auction call or while it shares a live order
+    /// group with another symbol; finished groups spanning symbols stay
+    /// behind. Nothing else may be submitted for the symbol while it is
+    /// detached.
+    pub fn detach_symbol(&self, symbol: &str) -> Result<SymbolState, String> {
+        let instrument = self
+            .get_instrument(symbol)
+            .ok_or_else(|| format!("Unknown symbol {}", symbol))?;
+
+        if self.orderbooks.get(symbol).is_some_and(|book| book.auction.is_some()) {
+            return Err(format!("{} is in an auction call", symbol));
+        }
+
+        // Every order of each group, including parents whose children have been released
+        let mut members: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+        for leg in self.group_legs.iter() {
+            members.entry(*leg.value()).or_default().push(*leg.key());
+        }
+        let candidates: Vec<OrderGroup> = self.groups.iter().map(|group| group.clone()).collect();
+        let mut groups = Vec::new();
+        for group in candidates {
+            let symbols: BTreeSet<String> = members
+                .get(&group.id)
+                .into_iter()
+                .flatten()
+                .filter_map(|order_id| self.orders.get(order_id).map(|order| order.symbol.clone()))
+                .chain(group.pending.iter().map(|child| child.symbol.clone()))
+                .collect();
+            if !symbols.contains(symbol) {
+                continue;
+            }
+            if symbols.len() == 1 {
+                groups.push(group);
+            } else if matches!(group.status, GroupStatus::Working | GroupStatus::Triggered) {
+                return Err(format!("{} shares an order group with another symbol", symbol));
+            }
+        }
+
+        // Nothing new can be submitted once the instrument is gone
+        self.instruments.remove(symbol);
+        let book = self.orderbooks.remove(symbol).map(|(_, book)| book);
+        let stops = self.stop_books.remove(symbol).map(|(_, stops)| stops);
+
+        let order_ids: Vec<Uuid> = self
+            .orders
+            .iter()
+            .filter(|order| order.symbol == symbol)
+            .map(|order| order.id)
+            .collect();
+        let orders: Vec<Order> = order_ids
+            .into_iter()
+            .filter_map(|order_id| self.orders.remove(&order_id).map(|(_, order)| order))
+            .collect();
+
+        let group_legs: Vec<(Uuid, Uuid)> = groups
+            .iter()
+            .flat_map(|group| members.remove(&group.id).unwrap_or_default().into_iter().map(|id| (id, group.id)))
+            .collect();
+        for (order_id, _) in &group_legs {
+            self.group_legs.remove(order_id);
+        }
+        for group in &groups {
+            self.groups.remove(&group.id);
+        }
+
+        // Sessions stay registered here too, for the users' other symbols
+        let users: BTreeSet<&str> = orders.iter().map(|order| order.user_id.as_str()).collect();
+        let sessions: Vec<(String, String)> = self
+            .cancel_on_disconnect
+            .iter()
+            .filter(|session| users.contains(session.value().as_str()))
+            .map(|session| (session.key().clone(), session.value().clone()))
+            .collect();
+
+        let quotes: Vec<MakerQuote> = self
+            .quotes
+            .iter()
+            .filter(|quote| quote.symbol == symbol)
+            .map(|quote| quote.clone())
+            .collect();
+        self.quotes.retain(|(_, quote_symbol), _| quote_symbol != symbol);
+
+        Ok(SymbolState {
+            instrument,
+            book,
+            stops,
+            algorithm: self.algorithms.remove(symbol).map(|(_, algorithm)| algorithm),
+            session: self.sessions.remove(symbol).map(|(_, session)| session),
+            price_bands: self.price_bands.remove(symbol).map(|(_, bands)| bands),
+            timetable: self.timetables.remove(symbol).map(|(_, timetable)| timetable),
+            orders,
+            quotes,
+            groups,
+            group_legs,
+            sessions,
+        })
+    }
+
+    /// Takes over a symbol detached from another engine, with its book,
+    /// trading state and order history. Fails if the engine already trades
+    /// the symbol, handing the state back so it is not lost.
+    pub fn attach_symbol(&self, state: SymbolState) -> Result<(), (String, Box<SymbolState>)> {
+        let symbol = state.instrument.symbol.clone();
+        if self.instruments.contains_key(&symbol) {
+            return Err((format!("{} is already registered", symbol), Box::new(state)));
+        }
+
+        if let Some(book) = state.book {
+            self.orderbooks.insert(symbol.clone(), book);
+        }
+        if let Some(stops) = state.stops {
+            self.stop_books.insert(symbol.clone(), stops);
+        }
+        if let Some(algorithm) = state.algorithm {
+            self.algorithms.insert(symbol.clone(), algorithm);
+        }
+        if let Some(session) = state.session {
+            self.sessions.insert(symbol.clone(), session);
+        }
+        if let Some(bands) = state.price_bands {
+            self.price_bands.insert(symbol.clone(), bands);
+        }
+        if let Some(timetable) = state.timetable {
+            self.timetables.insert(symbol.clone(), timetable);
+        }
+        for order in state.orders {
+            self.orders.insert(order.id, order);
+        }
+        for quote in state.quotes {
+            self.quotes.insert((quote.user_id.clone(), quote.symbol.clone()), quote);
+        }
+        for group in state.groups {
+            self.groups.insert(group.id, group);
+        }
+        for (order_id, group_id) in state.group_legs {
+            self.group_legs.insert(order_id, group_id);
+        }
+        for (session_id, user_id) in state.sessions {
+            self.cancel_on_disconnect.entry(session_id).or_insert(user_id);
+        }
+
+        // Registering last makes the symbol tradable only once it is complete
+        self.register_instrument(state.instrument);
+        Ok(())
+    }
+}
+
+impl Default for MatchingEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{ProRata, RestingOrder};
+    use crate::models::{PegType, TrailingOffset};
+    use rust_decimal_macros::dec;
+
+    fn engine() -> MatchingEngine {
+        let engine = MatchingEngine::new();
+        for symbol in ["AAPL", "MSFT"] {
+            engine.register_instrument(Instrument::new(symbol, dec!(0.01), "USD"));
+        }
+        engine
+    }
+
+    /// Checks every book against its own invariants and each resting entry
+    /// against the order it stands for.
+    fn assert_consistent(engine: &MatchingEngine) {
+        for symbol in ["AAPL", "MSFT"] {
+            let book = engine.get_orderbook(symbol).unwrap();
+            book.verify_invariants().unwrap();
+
+            for level in book.bids.values().chain(book.asks.values()) {
+                for entry in book.queue(level) {
+                    let order = engine.get_order(entry.order_id).unwrap();
+                    assert!(order.is_active(), "{} rests while {:?}", order.id, order.status);
+                    assert_eq!(book.qty_value(entry.remaining), order.remaining_quantity());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_limit_order_matching() {
+        let engine = engine();
+
+        let sell_order = Order::new(
+            "AAPL".to_string(),
+            OrderSide::Sell,
+            OrderType::Limit,
+            dec!(100),
+            Some(dec!(150.00)),
+            None,
+            "seller".to_string(),
+        );
+
+        let buy_order = Order::new(
+            "AAPL".to_string(),
+            OrderSide::Buy,
+            OrderType::Limit,
+            dec!(100),
+            Some(dec!(150.00)),
+            None,
+            "buyer".to_string(),
+        );
+
+        engine.submit_order(sell_order).unwrap();
+        let trades = engine.submit_order(buy_order).unwrap();
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, dec!(100));
+        assert_eq!(trades[0].price, dec!(150.00));
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_partial_fill() {
+        let engine = engine();
+
+        let sell_order = Order::new(
+            "AAPL".to_string(),
+            OrderSide::Sell,
+            OrderType::Limit,
+            dec!(50),
+            Some(dec!(150.00)),
+            None,
+            "seller".to_string(),
+        );
+
+        let buy_order = Order::new(
+            "AAPL".to_string(),
+            OrderSide::Buy,
+            OrderType::Limit,
+            dec!(100),
+            Some(dec!(150.00)),
+            None,
+            "buyer".to_string(),
+        );
+
+        engine.submit_order(sell_order).unwrap();
+        let trades = engine.submit_order(buy_order.clone()).unwrap();
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, dec!(50));
+
+        let stored_order = engine.get_order(buy_order.id).unwrap();
+        assert_eq!(stored_order.filled_quantity, dec!(50));
+        assert_eq!(stored_order.status, OrderStatus::PartiallyFilled);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_order_cancellation() {
+        let engine = engine();
+
+        let order = Order::new(
+            "AAPL".to_string(),
+            OrderSide::Buy,
+            OrderType::Limit,
+            dec!(100),
+            Some(dec!(150.00)),
+            None,
+            "buyer".to_string(),
+        );
+
+        let order_id = order.id;
+        engine.submit_order(order).unwrap();
+        
+        assert!(engine.cancel_order(order_id).is_ok());
+        
+        let cancelled_order = engine.get_order(order_id).unwrap();
+        assert_eq!(cancelled_order.status, OrderStatus::Cancelled);
+
+        assert_consistent(&engine);
+    }
+
+    fn limit_order(side: OrderSide, quantity: Decimal, price: Decimal, user: &str) -> Order {
+        Order::new(
+            "AAPL".to_string(),
+            side,
+            OrderType::Limit,
+            quantity,
+            Some(price),
+            None,
+            user.to_string(),
+        )
+    }
+
+    fn stop_loss_order(side: OrderSide, quantity: Decimal, stop_price: Decimal) -> Order {
+        Order::new(
+            "AAPL".to_string(),
+            side,
+            OrderType::StopLoss,
+            quantity,
+            None,
+            Some(stop_price),
+            "stopper".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_stop_loss_waits_for_trigger() {
+        let engine = engine();
+
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(100), dec!(150.00), "bidder")).unwrap();
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(100), dec!(149.00), "bidder")).unwrap();
+
+        let stop = stop_loss_order(OrderSide::Sell, dec!(50), dec!(150.00));
+        let stop_id = stop.id;
+        assert!(engine.submit_order(stop).unwrap().is_empty());
+
+        // The stop must not rest in the visible book
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert!(book.asks.is_empty());
+        assert_eq!(engine.get_order(stop_id).unwrap().status, OrderStatus::Pending);
+
+        let trades = engine
+            .submit_order(limit_order(OrderSide::Sell, dec!(100), dec!(150.00), "seller"))
+            .unwrap();
+
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[1].seller_order_id, stop_id);
+        assert_eq!(trades[1].price, dec!(149.00));
+        assert_eq!(engine.get_order(stop_id).unwrap().status, OrderStatus::Filled);
+
+        let events = engine.drain_events();
+        assert!(matches!(
+            events.as_slice(),
+            [EngineEvent::StopTriggered { order_id, .. }] if *order_id == stop_id
+        ));
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_cascading_stops_trigger_in_order() {
+        let engine = engine();
+
+        for price in [dec!(150.00), dec!(149.00), dec!(148.00)] {
+            engine.submit_order(limit_order(OrderSide::Buy, dec!(10), price, "bidder")).unwrap();
+        }
+
+        let lower_stop = stop_loss_order(OrderSide::Sell, dec!(10), dec!(149.00));
+        let upper_stop = stop_loss_order(OrderSide::Sell, dec!(10), dec!(150.00));
+        let (lower_id, upper_id) = (lower_stop.id, upper_stop.id);
+        engine.submit_order(lower_stop).unwrap();
+        engine.submit_order(upper_stop).unwrap();
+
+        let trades = engine
+            .submit_order(limit_order(OrderSide::Sell, dec!(10), dec!(150.00), "seller"))
+            .unwrap();
+
+        let prices: Vec<Decimal> = trades.iter().map(|t| t.price).collect();
+        assert_eq!(prices, vec![dec!(150.00), dec!(149.00), dec!(148.00)]);
+
+        let triggered: Vec<Uuid> = engine
+            .drain_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                EngineEvent::StopTriggered { order_id, .. } => Some(order_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(triggered, vec![upper_id, lower_id]);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_cancel_pending_stop() {
+        let engine = engine();
+
+        let bid = limit_order(OrderSide::Buy, dec!(100), dec!(150.00), "bidder");
+        let bid_id = bid.id;
+        engine.submit_order(bid).unwrap();
+
+        let stop = stop_loss_order(OrderSide::Sell, dec!(50), dec!(150.00));
+        let stop_id = stop.id;
+        engine.submit_order(stop).unwrap();
+        engine.cancel_order(stop_id).unwrap();
+
+        let trades = engine
+            .submit_order(limit_order(OrderSide::Sell, dec!(10), dec!(150.00), "seller"))
+            .unwrap();
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(engine.get_order(stop_id).unwrap().status, OrderStatus::Cancelled);
+        assert_eq!(engine.get_order(bid_id).unwrap().filled_quantity, dec!(10));
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_ioc_cancels_remainder() {
+        let engine = engine();
+
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(40), dec!(150.00), "seller")).unwrap();
+
+        let ioc = limit_order(OrderSide::Buy, dec!(100), dec!(150.00), "buyer")
+            .with_time_in_force(TimeInForce::Ioc);
+        let ioc_id = ioc.id;
+        let trades = engine.submit_order(ioc).unwrap();
+
+        assert_eq!(trades.len(), 1);
+        let stored = engine.get_order(ioc_id).unwrap();
+        assert_eq!(stored.filled_quantity, dec!(40));
+        assert_eq!(stored.status, OrderStatus::Cancelled);
+        assert!(engine.get_orderbook("AAPL").unwrap().bids.is_empty());
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_fok_requires_full_quantity() {
+        let engine = engine();
+
+        let ask = limit_order(OrderSide::Sell, dec!(40), dec!(150.00), "seller");
+        let ask_id = ask.id;
+        engine.submit_order(ask).unwrap();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(40), dec!(151.00), "seller")).unwrap();
+
+        let killed = limit_order(OrderSide::Buy, dec!(60), dec!(150.00), "buyer")
+            .with_time_in_force(TimeInForce::Fok);
+        let killed_id = killed.id;
+        assert!(engine.submit_order(killed).unwrap().is_empty());
+        assert_eq!(engine.get_order(killed_id).unwrap().status, OrderStatus::Cancelled);
+        assert_eq!(engine.get_order(ask_id).unwrap().filled_quantity, Decimal::ZERO);
+
+        let filled = limit_order(OrderSide::Buy, dec!(60), dec!(151.00), "buyer")
+            .with_time_in_force(TimeInForce::Fok);
+        let filled_id = filled.id;
+        let trades = engine.submit_order(filled).unwrap();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(engine.get_order(filled_id).unwrap().status, OrderStatus::Filled);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_expire_day_and_gtd_orders() {
+        let engine = engine();
+
+        let gtc = limit_order(OrderSide::Buy, dec!(100), dec!(148.00), "buyer");
+        let mut day = limit_order(OrderSide::Buy, dec!(100), dec!(149.00), "buyer")
+            .with_time_in_force(TimeInForce::Day);
+        day.timestamp = "2024-03-01T10:00:00Z".parse().unwrap();
+        let gtd_expiry = day.timestamp + chrono::Duration::hours(2);
+        let mut gtd = limit_order(OrderSide::Sell, dec!(100), dec!(151.00), "seller")
+            .with_time_in_force(TimeInForce::Gtd(gtd_expiry));
+        gtd.timestamp = day.timestamp;
+        let (gtc_id, day_id, gtd_id) = (gtc.id, day.id, gtd.id);
+
+        engine.submit_order(gtc).unwrap();
+        engine.submit_order(day).unwrap();
+        engine.submit_order(gtd).unwrap();
+
+        // A mid-day sweep leaves DAY orders alone and only catches GTD expiries
+        assert!(engine.expire_orders(gtd_expiry - chrono::Duration::hours(1)).is_empty());
+        assert_eq!(engine.expire_orders(gtd_expiry), vec![gtd_id]);
+        assert!(engine.get_orderbook("AAPL").unwrap().asks.is_empty());
+        assert_eq!(engine.get_order(day_id).unwrap().status, OrderStatus::Pending);
+
+        let next_day = "2024-03-02T00:00:00Z".parse().unwrap();
+        assert_eq!(engine.expire_orders(next_day), vec![day_id]);
+        assert_eq!(engine.get_order(day_id).unwrap().status, OrderStatus::Expired);
+        assert_eq!(engine.get_orderbook("AAPL").unwrap().best_bid(), Some(dec!(148.00)));
+        assert_eq!(engine.get_order(gtc_id).unwrap().status, OrderStatus::Pending);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_expiry_updates_each_symbol_once() {
+        let engine = engine();
+        engine.start_auction("AAPL", AuctionKind::Opening);
+
+        let gtd = |symbol: &str, price: Decimal| {
+            let order = Order::new(
+                symbol.to_string(),
+                OrderSide::Buy,
+                OrderType::Limit,
+                dec!(10),
+                Some(price),
+                None,
+                "buyer".to_string(),
+            );
+            let expiry = order.timestamp + chrono::Duration::hours(1);
+            order.with_time_in_force(TimeInForce::Gtd(expiry))
+        };
+        // Interleaved by entry time, so the expired symbols alternate
+        let orders = [gtd("AAPL", dec!(100.00)), gtd("MSFT", dec!(300.00)), gtd("AAPL", dec!(101.00))];
+        let later = orders[2].timestamp + chrono::Duration::hours(2);
+        for order in orders {
+            engine.submit_order(order).unwrap();
+        }
+        engine.drain_events();
+
+        assert_eq!(engine.expire_orders(later).len(), 3);
+        let indicative: Vec<String> = engine
+            .drain_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                EngineEvent::AuctionIndicative { symbol, uncross, .. } => {
+                    assert!(uncross.is_none());
+                    Some(symbol)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(indicative, vec!["AAPL".to_string()]);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_post_only_reject() {
+        let engine = engine();
+
+        let ask = limit_order(OrderSide::Sell, dec!(100), dec!(150.00), "seller");
+        let ask_id = ask.id;
+        engine.submit_order(ask).unwrap();
+
+        let maker = limit_order(OrderSide::Buy, dec!(100), dec!(150.00), "maker")
+            .with_post_only(PostOnly::Reject);
+        let maker_id = maker.id;
+        assert!(engine.submit_order(maker).is_err());
+
+        assert_eq!(engine.get_order(maker_id).unwrap().status, OrderStatus::Rejected);
+        assert_eq!(engine.get_order(ask_id).unwrap().filled_quantity, Decimal::ZERO);
+        assert!(engine.get_orderbook("AAPL").unwrap().bids.is_empty());
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_post_only_slide() {
+        let engine = engine();
+        engine.register_instrument(Instrument::new("AAPL", dec!(0.05), "USD"));
+
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(100), dec!(150.00), "seller")).unwrap();
+
+        let maker = limit_order(OrderSide::Buy, dec!(100), dec!(150.50), "maker")
+            .with_post_only(PostOnly::Slide);
+        let maker_id = maker.id;
+        assert!(engine.submit_order(maker).unwrap().is_empty());
+
+        let stored = engine.get_order(maker_id).unwrap();
+        assert_eq!(stored.price, Some(dec!(149.95)));
+        assert_eq!(stored.status, OrderStatus::Pending);
+        assert_eq!(engine.get_orderbook("AAPL").unwrap().best_bid(), Some(dec!(149.95)));
+
+        // A non-crossing post-only order rests at its own price
+        let passive = limit_order(OrderSide::Buy, dec!(100), dec!(149.50), "maker")
+            .with_post_only(PostOnly::Slide);
+        let passive_id = passive.id;
+        engine.submit_order(passive).unwrap();
+        assert_eq!(engine.get_order(passive_id).unwrap().price, Some(dec!(149.50)));
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_iceberg_hides_reserve_and_refreshes() {
+        let engine = engine();
+
+        let iceberg = limit_order(OrderSide::Sell, dec!(300), dec!(150.00), "iceberg")
+            .with_display_quantity(dec!(100));
+        let lit = limit_order(OrderSide::Sell, dec!(50), dec!(150.00), "lit");
+        let (iceberg_id, lit_id) = (iceberg.id, lit.id);
+        engine.submit_order(iceberg).unwrap();
+        engine.submit_order(lit).unwrap();
+
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.depth(OrderSide::Sell, 1), vec![(dec!(150.00), dec!(150))]);
+
+        // Takes the visible slice, then the lit order, then the refreshed slice
+        let trades = engine
+            .submit_order(limit_order(OrderSide::Buy, dec!(170), dec!(150.00), "buyer"))
+            .unwrap();
+        let fills: Vec<(Uuid, Decimal)> = trades.iter().map(|t| (t.seller_order_id, t.quantity)).collect();
+        assert_eq!(fills, vec![(iceberg_id, dec!(100)), (lit_id, dec!(50)), (iceberg_id, dec!(20))]);
+
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.depth(OrderSide::Sell, 1), vec![(dec!(150.00), dec!(80))]);
+        assert_eq!(engine.get_order(iceberg_id).unwrap().remaining_quantity(), dec!(180));
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_trailing_stop_follows_last_trade() {
+        let engine = engine();
+
+        // Prints a trade at `price` between two throwaway users
+        let print = |price: Decimal| {
+            engine.submit_order(limit_order(OrderSide::Sell, dec!(1), price, "printer")).unwrap();
+            engine.submit_order(limit_order(OrderSide::Buy, dec!(1), price, "printer")).unwrap()
+        };
+
+        print(dec!(100.00));
+
+        let trailing = Order::new(
+            "AAPL".to_string(),
+            OrderSide::Sell,
+            OrderType::StopLoss,
+            dec!(10),
+            None,
+            None,
+            "stopper".to_string(),
+        )
+        .with_trailing_stop(TrailingOffset::Amount(dec!(1.00)), TrailReference::LastTrade);
+        let trailing_id = trailing.id;
+        engine.submit_order(trailing).unwrap();
+        assert_eq!(engine.get_order(trailing_id).unwrap().stop_price, Some(dec!(99.00)));
+
+        print(dec!(102.00));
+        assert_eq!(engine.get_order(trailing_id).unwrap().stop_price, Some(dec!(101.00)));
+
+        // Adverse moves never loosen the stop
+        print(dec!(101.50));
+        assert_eq!(engine.get_order(trailing_id).unwrap().stop_price, Some(dec!(101.00)));
+
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(10), dec!(100.00), "bidder")).unwrap();
+        let trades = print(dec!(101.00));
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[1].seller_order_id, trailing_id);
+        assert_eq!(engine.get_order(trailing_id).unwrap().status, OrderStatus::Filled);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_primary_peg_follows_best_bid() {
+        let engine = engine();
+
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(100), dec!(100.00), "bidder")).unwrap();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(100), dec!(101.00), "seller")).unwrap();
+
+        let pegged = Order::new(
+            "AAPL".to_string(),
+            OrderSide::Buy,
+            OrderType::Limit,
+            dec!(50),
+            None,
+            None,
+            "pegger".to_string(),
+        )
+        .with_peg(PegType::Primary, None, Some(dec!(100.40)));
+        let pegged_id = pegged.id;
+        engine.submit_order(pegged).unwrap();
+        assert_eq!(engine.get_order(pegged_id).unwrap().price, Some(dec!(100.00)));
+
+        let improver = limit_order(OrderSide::Buy, dec!(100), dec!(100.20), "improver");
+        let improver_id = improver.id;
+        engine.submit_order(improver).unwrap();
+
+        // Repriced orders queue behind orders already at the new level
+        let book = engine.get_orderbook("AAPL").unwrap();
+        let level = book.level(OrderSide::Buy, dec!(100.20)).unwrap();
+        let queue: Vec<Uuid> = book.queue(level).map(|e| e.order_id).collect();
+        assert_eq!(queue, vec![improver_id, pegged_id]);
+        assert_eq!(book.qty_value(level.lit_quantity), dec!(150));
+
+        // The cap holds the peg back
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(100), dec!(100.50), "improver")).unwrap();
+        assert_eq!(engine.get_order(pegged_id).unwrap().price, Some(dec!(100.40)));
+
+        engine.cancel_order(improver_id).unwrap();
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.depth(OrderSide::Buy, 3), vec![
+            (dec!(100.50), dec!(100)),
+            (dec!(100.40), dec!(50)),
+            (dec!(100.00), dec!(100)),
+        ]);
+        assert!(engine
+            .drain_events()
+            .iter()
+            .any(|e| matches!(e, EngineEvent::PegRepriced { new_price, .. } if *new_price == dec!(100.40))));
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_midpoint_peg_rests_at_half_tick() {
+        let engine = engine();
+
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(100), dec!(100.00), "bidder")).unwrap();
+        let ask = limit_order(OrderSide::Sell, dec!(100), dec!(100.01), "seller");
+        let ask_id = ask.id;
+        engine.submit_order(ask).unwrap();
+
+        let midpoint = Order::new(
+            "AAPL".to_string(),
+            OrderSide::Sell,
+            OrderType::Limit,
+            dec!(30),
+            None,
+            None,
+            "pegger".to_string(),
+        )
+        .with_peg(PegType::Midpoint, None, None);
+        let midpoint_id = midpoint.id;
+        engine.submit_order(midpoint).unwrap();
+
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.best_ask(), Some(dec!(100.005)));
+        assert_eq!(book.reference_prices(), (Some(dec!(100.00)), Some(dec!(100.01))));
+
+        // Widening the spread moves the peg to the new midpoint
+        engine.cancel_order(ask_id).unwrap();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(100), dec!(100.03), "seller")).unwrap();
+        assert_eq!(engine.get_order(midpoint_id).unwrap().price, Some(dec!(100.015)));
+
+        let trades = engine
+            .submit_order(limit_order(OrderSide::Buy, dec!(30), dec!(100.02), "buyer"))
+            .unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price, dec!(100.015));
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_peg_prices_round_passively_onto_the_grid() {
+        let engine = engine();
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(100), dec!(100.00), "bidder")).unwrap();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(100), dec!(100.01), "seller")).unwrap();
+
+        let pegged = |side, peg_type, offset| {
+            Order::new("AAPL".to_string(), side, OrderType::Limit, dec!(10), None, None, "pegger".to_string())
+                .with_peg(peg_type, Some(offset), None)
+        };
+        let buy = pegged(OrderSide::Buy, PegType::Primary, dec!(0.003));
+        let sell = pegged(OrderSide::Sell, PegType::Midpoint, dec!(-0.002));
+        let (buy_id, sell_id) = (buy.id, sell.id);
+        engine.submit_order(buy).unwrap();
+        engine.submit_order(sell).unwrap();
+
+        // Both pegs land on 100.003, between two points of the half-tick grid
+        assert_eq!(engine.get_order(buy_id).unwrap().price, Some(dec!(100.00)));
+        assert_eq!(engine.get_order(sell_id).unwrap().price, Some(dec!(100.005)));
+        assert_eq!(engine.get_orderbook("AAPL").unwrap().best_ask(), Some(dec!(100.005)));
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_amend_priority_rules() {
+        let engine = engine();
+
+        let first = limit_order(OrderSide::Buy, dec!(100), dec!(100.00), "first");
+        let second = limit_order(OrderSide::Buy, dec!(100), dec!(100.00), "second");
+        let (first_id, second_id) = (first.id, second.id);
+        engine.submit_order(first).unwrap();
+        engine.submit_order(second).unwrap();
+
+        let queue = |engine: &MatchingEngine| -> Vec<Uuid> {
+            let book = engine.get_orderbook("AAPL").unwrap();
+            book.queue(book.level(OrderSide::Buy, dec!(100.00)).unwrap()).map(|e| e.order_id).collect()
+        };
+
+        // Reducing keeps the place in the queue
+        engine.amend_order(first_id, Some(dec!(60)), None).unwrap();
+        assert_eq!(queue(&engine), vec![first_id, second_id]);
+        assert_eq!(engine.get_orderbook("AAPL").unwrap().depth(OrderSide::Buy, 1)[0].1, dec!(160));
+
+        // Increasing sends it to the back
+        engine.amend_order(first_id, Some(dec!(150)), None).unwrap();
+        assert_eq!(queue(&engine), vec![second_id, first_id]);
+        assert_eq!(engine.get_orderbook("AAPL").unwrap().depth(OrderSide::Buy, 1)[0].1, dec!(250));
+
+        // A crossing price change trades straight away
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(40), dec!(100.50), "seller")).unwrap();
+        let trades = engine.amend_order(second_id, None, Some(dec!(100.50))).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, dec!(40));
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.depth(OrderSide::Buy, 2), vec![(dec!(100.50), dec!(60)), (dec!(100.00), dec!(150))]);
+
+        assert!(engine.amend_order(second_id, Some(dec!(40)), None).is_err());
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_replace_links_orders() {
+        let engine = engine();
+
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(50), dec!(101.00), "seller")).unwrap();
+
+        let original = limit_order(OrderSide::Buy, dec!(100), dec!(100.00), "buyer");
+        let original_id = original.id;
+        engine.submit_order(original).unwrap();
+
+        let replacement = limit_order(OrderSide::Buy, dec!(80), dec!(101.00), "buyer");
+        let replacement_id = replacement.id;
+        let trades = engine.replace_order(original_id, replacement).unwrap();
+        assert_eq!(trades.len(), 1);
+
+        let original = engine.get_order(original_id).unwrap();
+        assert_eq!(original.status, OrderStatus::Replaced);
+        assert_eq!(original.replaced_by, Some(replacement_id));
+
+        let replacement = engine.get_order(replacement_id).unwrap();
+        assert_eq!(replacement.replaces, Some(original_id));
+        assert_eq!(replacement.status, OrderStatus::PartiallyFilled);
+
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.depth(OrderSide::Buy, 2), vec![(dec!(101.00), dec!(30))]);
+        assert!(engine
+            .replace_order(original_id, limit_order(OrderSide::Buy, dec!(10), dec!(99.00), "buyer"))
+            .is_err());
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_rejected_change_keeps_original_working() {
+        let engine = engine();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(50), dec!(101.00), "seller")).unwrap();
+
+        let original = limit_order(OrderSide::Buy, dec!(100), dec!(100.00), "buyer").with_post_only(PostOnly::Reject);
+        let original_id = original.id;
+        engine.submit_order(original).unwrap();
+
+        // A post-only amend that would cross is rejected and changes nothing
+        assert!(engine.amend_order(original_id, Some(dec!(120)), Some(dec!(101.00))).is_err());
+        let order = engine.get_order(original_id).unwrap();
+        assert_eq!((order.status, order.quantity, order.price), (OrderStatus::Pending, dec!(100), Some(dec!(100.00))));
+
+        // So is a replacement that would cross
+        let replacement = limit_order(OrderSide::Buy, dec!(80), dec!(101.00), "buyer").with_post_only(PostOnly::Reject);
+        let replacement_id = replacement.id;
+        assert!(engine.replace_order(original_id, replacement).is_err());
+        assert_eq!(engine.get_order(original_id).unwrap().status, OrderStatus::Pending);
+        assert!(engine.get_order(replacement_id).is_none());
+
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.depth(OrderSide::Buy, 2), vec![(dec!(100.00), dec!(100))]);
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_concurrent_replace_and_match() {
+        let engine = engine();
+
+        // One user keeps replacing a resting bid while others sell into it
+        let replace = |engine: &MatchingEngine| -> Vec<Uuid> {
+            let bid = limit_order(OrderSide::Buy, dec!(10), dec!(100.00), "buyer");
+            let mut working = bid.id;
+            let mut submitted = vec![bid.id];
+            engine.submit_order(bid).unwrap();
+
+            for i in 0..300 {
+                let price = dec!(100.00) - Decimal::new(i % 3, 2);
+                let replacement = limit_order(OrderSide::Buy, dec!(10), price, "buyer");
+                submitted.push(replacement.id);
+
+                // The bid may have filled completely since the last replace
+                if engine.replace_order(working, replacement.clone()).is_err() {
+                    engine.submit_order(replacement.clone()).unwrap();
+                }
+                working = replacement.id;
+            }
+            submitted
+        };
+        let sell = |engine: &MatchingEngine, user: &str| -> Vec<Uuid> {
+            (0..300)
+                .map(|i| {
+                    let order = Order::new(
+                        "AAPL".to_string(),
+                        OrderSide::Sell,
+                        OrderType::Limit,
+                        Decimal::from(1 + i % 3),
+                        Some(dec!(99.98)),
+                        None,
+                        user.to_string(),
+                    )
+                    .with_time_in_force(TimeInForce::Ioc);
+                    let order_id = order.id;
+                    engine.submit_order(order).unwrap();
+                    order_id
+                })
+                .collect()
+        };
+
+        let submitted: Vec<Uuid> = std::thread::scope(|scope| {
+            let engine = &engine;
+            let mut handles = vec![scope.spawn(move || replace(engine))];
+            for user in ["seller1", "seller2", "seller3"] {
+                handles.push(scope.spawn(move || sell(engine, user)));
+            }
+            handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+        });
+
+        assert_consistent(&engine);
+        let filled = |side| -> Decimal {
+            submitted
+                .iter()
+                .filter_map(|id| engine.get_order(*id))
+                .filter(|order| order.side == side)
+                .map(|order| order.filled_quantity)
+                .sum()
+        };
+        assert!(filled(OrderSide::Buy) > Decimal::ZERO);
+        assert_eq!(filled(OrderSide::Buy), filled(OrderSide::Sell));
+    }
+
+    fn prevented(engine: &MatchingEngine) -> Vec<(SelfTradePrevention, Decimal)> {
+        engine
+            .drain_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                EngineEvent::SelfTradePrevented { mode, quantity, .. } => Some((mode, quantity)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_self_trade_prevention_cancel_modes() {
+        let engine = engine();
+
+        let own_ask = limit_order(OrderSide::Sell, dec!(50), dec!(100.00), "trader");
+        let other_ask = limit_order(OrderSide::Sell, dec!(50), dec!(100.00), "other");
+        let (own_id, other_id) = (own_ask.id, other_ask.id);
+        engine.submit_order(own_ask).unwrap();
+        engine.submit_order(other_ask).unwrap();
+
+        // Without prevention the orders trade with each other
+        let wash = limit_order(OrderSide::Buy, dec!(10), dec!(100.00), "trader");
+        assert_eq!(engine.submit_order(wash).unwrap()[0].seller_order_id, own_id);
+
+        let resting = limit_order(OrderSide::Buy, dec!(60), dec!(100.00), "trader")
+            .with_self_trade_prevention(SelfTradePrevention::CancelResting);
+        let trades = engine.submit_order(resting).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].seller_order_id, other_id);
+        assert_eq!(engine.get_order(own_id).unwrap().status, OrderStatus::Cancelled);
+        assert_eq!(prevented(&engine), vec![(SelfTradePrevention::CancelResting, dec!(40))]);
+
+        let own_bid = limit_order(OrderSide::Buy, dec!(50), dec!(99.00), "trader");
+        let own_bid_id = own_bid.id;
+        engine.submit_order(own_bid).unwrap();
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(20), dec!(100.50), "other")).unwrap();
+
+        let aggressor = Order::new(
+            "AAPL".to_string(),
+            OrderSide::Sell,
+            OrderType::Market,
+            dec!(30),
+            None,
+            None,
+            "trader".to_string(),
+        )
+        .with_self_trade_prevention(SelfTradePrevention::CancelAggressing);
+        let aggressor_id = aggressor.id;
+
+        // The other user's bid trades first, then the own remainder at 100.00 stops the order
+        let trades = engine.submit_order(aggressor).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, dec!(20));
+        let aggressor = engine.get_order(aggressor_id).unwrap();
+        assert_eq!(aggressor.status, OrderStatus::Cancelled);
+        assert_eq!(aggressor.filled_quantity, dec!(20));
+        assert_eq!(engine.get_order(own_bid_id).unwrap().status, OrderStatus::Pending);
+        assert_eq!(prevented(&engine), vec![(SelfTradePrevention::CancelAggressing, dec!(10))]);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_self_trade_prevention_engine_default() {
+        let engine = engine().with_self_trade_prevention(SelfTradePrevention::DecrementAndCancel);
+
+        let own_ask = limit_order(OrderSide::Sell, dec!(100), dec!(100.00), "trader");
+        let own_id = own_ask.id;
+        engine.submit_order(own_ask).unwrap();
+
+        let bid = limit_order(OrderSide::Buy, dec!(30), dec!(100.00), "trader");
+        let bid_id = bid.id;
+        assert!(engine.submit_order(bid).unwrap().is_empty());
+
+        assert_eq!(engine.get_order(bid_id).unwrap().status, OrderStatus::Cancelled);
+        let own_ask = engine.get_order(own_id).unwrap();
+        assert_eq!(own_ask.remaining_quantity(), dec!(70));
+        assert_eq!(engine.get_orderbook("AAPL").unwrap().depth(OrderSide::Sell, 1)[0].1, dec!(70));
+        assert!(engine.get_orderbook("AAPL").unwrap().bids.is_empty());
+
+        // Per-order modes override the engine default
+        let both = limit_order(OrderSide::Buy, dec!(30), dec!(100.00), "trader")
+            .with_self_trade_prevention(SelfTradePrevention::CancelBoth);
+        engine.submit_order(both).unwrap();
+        assert_eq!(engine.get_order(own_id).unwrap().status, OrderStatus::Cancelled);
+        assert!(engine.get_orderbook("AAPL").unwrap().asks.is_empty());
+
+        assert_eq!(prevented(&engine), vec![
+            (SelfTradePrevention::DecrementAndCancel, dec!(30)),
+            (SelfTradePrevention::CancelBoth, dec!(30)),
+        ]);
+
+        assert_consistent(&engine);
+    }
+
+    fn market_order(side: OrderSide, quantity: Decimal) -> Order {
+        Order::new(
+            "AAPL".to_string(),
+            side,
+            OrderType::Market,
+            quantity,
+            None,
+            None,
+            "taker".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_market_order_cancels_unfilled_remainder() {
+        let engine = engine();
+
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(30), dec!(100.00), "seller")).unwrap();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(30), dec!(101.00), "seller")).unwrap();
+
+        let market = market_order(OrderSide::Buy, dec!(100));
+        let market_id = market.id;
+        let trades = engine.submit_order(market).unwrap();
+
+        assert_eq!(trades.len(), 2);
+        let stored = engine.get_order(market_id).unwrap();
+        assert_eq!(stored.filled_quantity, dec!(60));
+        assert_eq!(stored.status, OrderStatus::Cancelled);
+        assert!(engine.get_orderbook("AAPL").unwrap().asks.is_empty());
+
+        // An empty book fills nothing but still accepts the order
+        let unfilled = market_order(OrderSide::Buy, dec!(10));
+        let unfilled_id = unfilled.id;
+        assert!(engine.submit_order(unfilled).unwrap().is_empty());
+        assert_eq!(engine.get_order(unfilled_id).unwrap().status, OrderStatus::Cancelled);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_market_order_protection_price() {
+        let engine = engine();
+
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(30), dec!(100.00), "buyer")).unwrap();
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(30), dec!(99.50), "buyer")).unwrap();
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(30), dec!(90.00), "buyer")).unwrap();
+
+        let protected = market_order(OrderSide::Sell, dec!(100)).with_protection_price(dec!(99.00));
+        let protected_id = protected.id;
+        let trades = engine.submit_order(protected).unwrap();
+
+        let prices: Vec<Decimal> = trades.iter().map(|t| t.price).collect();
+        assert_eq!(prices, vec![dec!(100.00), dec!(99.50)]);
+        assert_eq!(engine.get_order(protected_id).unwrap().status, OrderStatus::Cancelled);
+        assert_eq!(engine.get_orderbook("AAPL").unwrap().best_bid(), Some(dec!(90.00)));
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_simulate_order_matches_submission() {
+        let engine = engine();
+
+        let iceberg = limit_order(OrderSide::Sell, dec!(300), dec!(150.00), "iceberg")
+            .with_display_quantity(dec!(100));
+        engine.submit_order(iceberg).unwrap();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(50), dec!(150.00), "lit")).unwrap();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(100), dec!(151.00), "lit")).unwrap();
+
+        let buy = limit_order(OrderSide::Buy, dec!(500), dec!(150.50), "buyer");
+        let before = engine.get_orderbook("AAPL").unwrap();
+        let simulated = engine.simulate_order(&buy).unwrap();
+
+        // Nothing changes until the order is really submitted
+        let after = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(after.depth(OrderSide::Sell, 5), before.depth(OrderSide::Sell, 5));
+        assert!(engine.get_order(buy.id).is_none());
+
+        assert_eq!(simulated.filled_quantity, dec!(350));
+        assert_eq!(simulated.leftover_quantity, dec!(150));
+        assert_eq!(simulated.average_price, Some(dec!(150.00)));
+
+        let trades = engine.submit_order(buy).unwrap();
+        let fills = |trades: &[Trade]| -> Vec<(Uuid, Decimal, Decimal)> {
+            trades.iter().map(|t| (t.seller_order_id, t.price, t.quantity)).collect()
+        };
+        assert_eq!(fills(&trades), fills(&simulated.trades));
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_simulate_fok_and_stop_orders() {
+        let engine = engine();
+
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(50), dec!(100.00), "seller")).unwrap();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(50), dec!(102.00), "seller")).unwrap();
+
+        let fok = limit_order(OrderSide::Buy, dec!(80), dec!(101.00), "buyer")
+            .with_time_in_force(TimeInForce::Fok);
+        let simulated = engine.simulate_order(&fok).unwrap();
+        assert!(simulated.trades.is_empty());
+        assert_eq!(simulated.leftover_quantity, dec!(80));
+
+        // Stops are costed as if they had just triggered
+        let stop = stop_loss_order(OrderSide::Buy, dec!(80), dec!(105.00));
+        let simulated = engine.simulate_order(&stop).unwrap();
+        assert_eq!(simulated.filled_quantity, dec!(80));
+        assert_eq!(simulated.average_price, Some(dec!(100.75)));
+        assert_eq!(engine.get_orderbook("AAPL").unwrap().depth(OrderSide::Sell, 2).len(), 2);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_pro_rata_symbol_allocation() {
+        let engine = engine();
+        engine.set_matching_algorithm("AAPL", ProRata);
+
+        let small = limit_order(OrderSide::Sell, dec!(20), dec!(100.00), "small");
+        let large = limit_order(OrderSide::Sell, dec!(80), dec!(100.00), "large");
+        let (small_id, large_id) = (small.id, large.id);
+        engine.submit_order(small).unwrap();
+        engine.submit_order(large).unwrap();
+
+        let trades = engine
+            .submit_order(limit_order(OrderSide::Buy, dec!(51), dec!(100.00), "buyer"))
+            .unwrap();
+        let fills: Vec<(Uuid, Decimal)> = trades.iter().map(|t| (t.seller_order_id, t.quantity)).collect();
+        assert_eq!(fills, vec![(small_id, dec!(11)), (large_id, dec!(40))]);
+
+        // Other symbols keep price-time priority
+        let msft = |side, quantity, user| {
+            let mut order = limit_order(side, quantity, dec!(100.00), user);
+            order.symbol = "MSFT".to_string();
+            order
+        };
+        let first = msft(OrderSide::Sell, dec!(20), "small");
+        let first_id = first.id;
+        engine.submit_order(first).unwrap();
+        engine.submit_order(msft(OrderSide::Sell, dec!(80), "large")).unwrap();
+
+        let trades = engine.submit_order(msft(OrderSide::Buy, dec!(20), "buyer")).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].seller_order_id, first_id);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_misbehaving_algorithm_is_held_to_available_quantity() {
+        // Always gives the first order 5 lots, even once it has been taken out
+        struct FrontFive;
+        impl MatchingAlgorithm for FrontFive {
+            fn allocate(&self, _quantity: Qty, resting: &[RestingOrder]) -> Vec<Qty> {
+                vec![Qty::new(5); resting.len().min(1)]
+            }
+        }
+
+        let engine = engine();
+        engine.set_matching_algorithm("AAPL", FrontFive);
+        let resting = limit_order(OrderSide::Sell, dec!(20), dec!(100.00), "seller").with_min_quantity(dec!(10));
+        let resting_id = resting.id;
+        engine.submit_order(resting).unwrap();
+
+        let trades = engine
+            .submit_order(limit_order(OrderSide::Buy, dec!(5), dec!(100.00), "buyer"))
+            .unwrap();
+        assert!(trades.is_empty());
+        assert_eq!(engine.get_order(resting_id).unwrap().filled_quantity, Decimal::ZERO);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_opening_auction_uncross() {
+        let engine = engine();
+        engine.start_auction("AAPL", AuctionKind::Opening);
+
+        let b1 = limit_order(OrderSide::Buy, dec!(100), dec!(10.10), "b1");
+        let b2 = limit_order(OrderSide::Buy, dec!(50), dec!(10.00), "b2");
+        let s1 = limit_order(OrderSide::Sell, dec!(80), dec!(9.90), "s1");
+        let s2 = limit_order(OrderSide::Sell, dec!(60), dec!(10.05), "s2");
+        let (b1_id, s1_id, s2_id) = (b1.id, s1.id, s2.id);
+
+        // Crossing orders rest during the call
+        for order in [b1, b2, s1, s2] {
+            assert!(engine.submit_order(order).unwrap().is_empty());
+        }
+        assert!(engine.submit_order(market_order(OrderSide::Buy, dec!(10))).is_err());
+
+        let indicative = engine.indicative_uncross("AAPL").unwrap();
+        assert_eq!((indicative.price, indicative.volume), (dec!(10.05), dec!(100)));
+        let published = engine
+            .drain_events()
+            .into_iter()
+            .filter(|event| matches!(event, EngineEvent::AuctionIndicative { .. }))
+            .count();
+        assert_eq!(published, 5);
+
+        let (trades, summary) = engine.uncross_auction("AAPL").unwrap();
+        let fills: Vec<(Uuid, Uuid, Decimal, Decimal)> = trades
+            .iter()
+            .map(|t| (t.buyer_order_id, t.seller_order_id, t.price, t.quantity))
+            .collect();
+        assert_eq!(
+            fills,
+            vec![(b1_id, s1_id, dec!(10.05), dec!(80)), (b1_id, s2_id, dec!(10.05), dec!(20))]
+        );
+        assert_eq!(summary.uncross, Some(indicative));
+        assert_eq!(summary.trade_count, 2);
+
+        // The rest of the book is uncrossed and continuous matching resumes
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.auction, None);
+        assert_eq!(book.best_bid(), Some(dec!(10.00)));
+        assert_eq!(book.depth(OrderSide::Sell, 1), vec![(dec!(10.05), dec!(40))]);
+        assert_eq!(book.last_trade_price, Some(dec!(10.05)));
+        assert_eq!(engine.submit_order(market_order(OrderSide::Buy, dec!(10))).unwrap().len(), 1);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_auction_fills_iceberg_beyond_its_display() {
+        let engine = engine();
+        engine.start_auction("AAPL", AuctionKind::Opening);
+
+        let iceberg = limit_order(OrderSide::Sell, dec!(100), dec!(10.00), "seller").with_display_quantity(dec!(10));
+        let iceberg_id = iceberg.id;
+        engine.submit_order(iceberg).unwrap();
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(35), dec!(10.00), "buyer")).unwrap();
+
+        // The whole 35 trades in one go, 10 from the slice and 25 from the reserve
+        let (trades, _) = engine.uncross_auction("AAPL").unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, dec!(35));
+        assert_eq!(engine.get_order(iceberg_id).unwrap().remaining_quantity(), dec!(65));
+
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.depth(OrderSide::Sell, 1), vec![(dec!(10.00), dec!(10))]);
+        assert_eq!(book.entry(iceberg_id).map(|entry| book.qty_value(entry.remaining)), Some(dec!(65)));
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_auction_leaves_out_unmet_execution_conditions() {
+        let engine = engine();
+        let msft = |mut order: Order| {
+            order.symbol = "MSFT".to_string();
+            order
+        };
+        engine.start_auction("AAPL", AuctionKind::Opening);
+        engine.start_auction("MSFT", AuctionKind::Opening);
+
+        // All-or-none ahead in time priority would only get 60 of its 100
+        let all_or_none = limit_order(OrderSide::Sell, dec!(100), dec!(10.00), "aon").with_all_or_none();
+        let plain = limit_order(OrderSide::Sell, dec!(40), dec!(10.00), "plain");
+        let (all_or_none_id, plain_id) = (all_or_none.id, plain.id);
+        engine.submit_order(all_or_none).unwrap();
+        engine.submit_order(plain).unwrap();
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(60), dec!(10.00), "buyer")).unwrap();
+
+        assert_eq!(engine.indicative_uncross("AAPL").unwrap().volume, dec!(40));
+        let (trades, _) = engine.uncross_auction("AAPL").unwrap();
+        let fills: Vec<(Uuid, Decimal)> = trades.iter().map(|t| (t.seller_order_id, t.quantity)).collect();
+        assert_eq!(fills, vec![(plain_id, dec!(40))]);
+        assert_eq!(engine.get_order(all_or_none_id).unwrap().filled_quantity, Decimal::ZERO);
+
+        // Nor is a minimum of 50 met by the 30 bid
+        let min_quantity = limit_order(OrderSide::Sell, dec!(100), dec!(10.00), "min").with_min_quantity(dec!(50));
+        let min_quantity = msft(min_quantity);
+        let plain = msft(limit_order(OrderSide::Sell, dec!(30), dec!(10.00), "plain"));
+        let (min_quantity_id, plain_id) = (min_quantity.id, plain.id);
+        engine.submit_order(min_quantity).unwrap();
+        engine.submit_order(plain).unwrap();
+        engine.submit_order(msft(limit_order(OrderSide::Buy, dec!(30), dec!(10.00), "buyer"))).unwrap();
+
+        let (trades, _) = engine.uncross_auction("MSFT").unwrap();
+        let fills: Vec<(Uuid, Decimal)> = trades.iter().map(|t| (t.seller_order_id, t.quantity)).collect();
+        assert_eq!(fills, vec![(plain_id, dec!(30))]);
+        assert_eq!(engine.get_order(min_quantity_id).unwrap().filled_quantity, Decimal::ZERO);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_session_phases() {
+        let engine = engine();
+        engine.set_session_state("AAPL", SessionState::Halted).unwrap();
+        engine.set_session_state("AAPL", SessionState::PreOpen).unwrap();
+
+        // Pre-open collects orders for the opening auction
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(100), dec!(10.00), "buyer")).unwrap();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(60), dec!(9.95), "seller")).unwrap();
+        let trades = engine.set_session_state("AAPL", SessionState::Continuous).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!((trades[0].price, trades[0].quantity), (dec!(9.95), dec!(60)));
+
+        // Halted only accepts cancels, closed accepts nothing
+        let resting = limit_order(OrderSide::Buy, dec!(10), dec!(9.00), "buyer");
+        let resting_id = resting.id;
+        engine.submit_order(resting).unwrap();
+        engine.set_session_state("AAPL", SessionState::Halted).unwrap();
+        assert!(engine.submit_order(limit_order(OrderSide::Buy, dec!(10), dec!(9.00), "buyer")).is_err());
+        assert!(engine.amend_order(resting_id, Some(dec!(5)), None).is_err());
+        engine.cancel_order(resting_id).unwrap();
+
+        engine.set_session_state("AAPL", SessionState::Closed).unwrap();
+        assert!(engine.set_session_state("AAPL", SessionState::Continuous).is_err());
+        assert!(engine.submit_order(limit_order(OrderSide::Buy, dec!(10), dec!(9.00), "buyer")).is_err());
+
+        let changes: Vec<(SessionState, SessionState)> = engine
+            .drain_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                EngineEvent::SessionChanged { from, to, .. } => Some((from, to)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (SessionState::Continuous, SessionState::Halted),
+                (SessionState::Halted, SessionState::PreOpen),
+                (SessionState::PreOpen, SessionState::Continuous),
+                (SessionState::Continuous, SessionState::Halted),
+                (SessionState::Halted, SessionState::Closed),
+            ]
+        );
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_timetable_drives_sessions() {
+        let engine = engine();
+        let at = |hour, minute| chrono::NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+        let on = |hour, minute| {
+            chrono::NaiveDate::from_ymd_opt(2024, 3, 1)
+                .unwrap()
+                .and_time(at(hour, minute))
+                .and_utc()
+        };
+
+        engine.set_timetable(
+            "AAPL",
+            Timetable::new(vec![
+                (at(9, 0), SessionState::PreOpen),
+                (at(9, 30), SessionState::Continuous),
+                (at(16, 0), SessionState::Closed),
+            ]),
+        );
+
+        engine.run_timetables(on(8, 0));
+        assert_eq!(engine.session_state("AAPL"), SessionState::Closed);
+        engine.run_timetables(on(9, 5));
+        assert_eq!(engine.session_state("AAPL"), SessionState::PreOpen);
+        engine.run_timetables(on(9, 30));
+        assert_eq!(engine.session_state("AAPL"), SessionState::Continuous);
+
+        // A manual halt holds until the next scheduled change
+        engine.set_session_state("AAPL", SessionState::Halted).unwrap();
+        engine.run_timetables(on(12, 0));
+        assert_eq!(engine.session_state("AAPL"), SessionState::Halted);
+        engine.run_timetables(on(16, 0));
+        assert_eq!(engine.session_state("AAPL"), SessionState::Closed);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_price_band_reject() {
+        let engine = engine();
+        engine.set_price_bands(
+            "AAPL",
+            PriceBands::new(BandAction::Reject).with_static_band(dec!(5), dec!(100.00)),
+        );
+
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(50), dec!(104.00), "seller")).unwrap();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(50), dec!(106.00), "seller")).unwrap();
+
+        // Walking through 105 rejects the whole order, including the part inside the band
+        let sweep = market_order(OrderSide::Buy, dec!(80));
+        let sweep_id = sweep.id;
+        assert!(engine.submit_order(sweep).is_err());
+        assert_eq!(engine.get_order(sweep_id).unwrap().status, OrderStatus::Rejected);
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.depth(OrderSide::Sell, 1), vec![(dec!(104.00), dec!(50))]);
+
+        assert_eq!(engine.submit_order(market_order(OrderSide::Buy, dec!(50))).unwrap().len(), 1);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_price_band_volatility_halt() {
+        let engine = engine();
+        engine.set_price_bands("AAPL", PriceBands::new(BandAction::Halt).with_dynamic_band(dec!(2)));
+
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(10), dec!(100.00), "seller")).unwrap();
+        engine.submit_order(market_order(OrderSide::Buy, dec!(10))).unwrap();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(10), dec!(101.00), "seller")).unwrap();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(10), dec!(103.00), "seller")).unwrap();
+        engine.drain_events();
+
+        // A fill-or-kill cut short by the band is killed without halting
+        let fok = limit_order(OrderSide::Buy, dec!(30), dec!(103.00), "buyer").with_time_in_force(TimeInForce::Fok);
+        let fok_id = fok.id;
+        assert!(engine.submit_order(fok).unwrap().is_empty());
+        assert_eq!(engine.get_order(fok_id).unwrap().status, OrderStatus::Cancelled);
+        assert_eq!(engine.session_state("AAPL"), SessionState::Continuous);
+        assert!(!engine
+            .drain_events()
+            .iter()
+            .any(|event| matches!(event, EngineEvent::VolatilityHalt { .. })));
+
+        // Fills at 101 inside the 2% band, then halts instead of trading at 103
+        let buy = limit_order(OrderSide::Buy, dec!(30), dec!(103.00), "buyer");
+        let buy_id = buy.id;
+        let trades = engine.submit_order(buy).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price, dec!(101.00));
+        assert_eq!(engine.get_order(buy_id).unwrap().remaining_quantity(), dec!(20));
+        assert_eq!(engine.session_state("AAPL"), SessionState::PreOpen);
+        let halted = engine.drain_events().into_iter().any(|event| {
+            matches!(event, EngineEvent::VolatilityHalt { order_id, .. } if order_id == buy_id)
+        });
+        assert!(halted);
+
+        // The re-opening auction uncrosses the resting remainder against 103
+        let trades = engine.set_session_state("AAPL", SessionState::Continuous).unwrap();
+        assert_eq!((trades[0].price, trades[0].quantity), (dec!(103.00), dec!(10)));
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_instrument_validation() {
+        let engine = engine();
+        engine.register_instrument(
+            Instrument::new("ES", dec!(0.25), "USD")
+                .with_tick_table(vec![(dec!(0), dec!(0.25)), (dec!(1000), dec!(0.50))])
+                .with_lot_size(dec!(5)),
+        );
+
+        let order = |symbol: &str, quantity, price| {
+            let mut order = limit_order(OrderSide::Buy, quantity, price, "buyer");
+            order.symbol = symbol.to_string();
+            order
+        };
+
+        let unknown = engine.submit_order(order("XYZ", dec!(10), dec!(10.00))).unwrap_err();
+        assert_eq!(unknown, "Unknown symbol XYZ");
+        assert!(engine.get_orderbook("XYZ").is_none());
+
+        let off_tick = engine.submit_order(order("ES", dec!(10), dec!(999.10))).unwrap_err();
+        assert!(off_tick.contains("tick size 0.25"), "{}", off_tick);
+        let off_tick = engine.submit_order(order("ES", dec!(10), dec!(1000.25))).unwrap_err();
+        assert!(off_tick.contains("tick size 0.50"), "{}", off_tick);
+        let bad_lot = engine.submit_order(order("ES", dec!(12), dec!(1000.50))).unwrap_err();
+        assert!(bad_lot.contains("lot size 5"), "{}", bad_lot);
+
+        engine.submit_order(order("ES", dec!(10), dec!(1000.50))).unwrap();
+        assert_eq!(engine.get_orderbook("ES").unwrap().best_bid(), Some(dec!(1000.50)));
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_mass_cancel_filters() {
+        let engine = engine();
+        let msft = |mut order: Order| {
+            order.symbol = "MSFT".to_string();
+            order
+        };
+
+        let aapl_bid = limit_order(OrderSide::Buy, dec!(10), dec!(99.00), "mm");
+        let aapl_ask = limit_order(OrderSide::Sell, dec!(10), dec!(101.00), "mm");
+        let msft_bid = msft(limit_order(OrderSide::Buy, dec!(10), dec!(49.00), "mm"));
+        let stop = stop_loss_order(OrderSide::Sell, dec!(10), dec!(95.00));
+        let other = limit_order(OrderSide::Buy, dec!(10), dec!(99.00), "other");
+        let ids = [aapl_bid.id, aapl_ask.id, msft_bid.id, stop.id, other.id];
+        for order in [aapl_bid, aapl_ask, msft_bid, stop, other] {
+            engine.submit_order(order).unwrap();
+        }
+
+        let filter = MassCancelFilter::default().with_user("mm").with_side(OrderSide::Buy);
+        assert_eq!(engine.mass_cancel(&filter), vec![ids[0], ids[2]]);
+        assert!(engine.get_orderbook("MSFT").unwrap().bids.is_empty());
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.depth(OrderSide::Buy, 5), vec![(dec!(99.00), dec!(10))]);
+
+        // Parked stops are pulled from the stop book too
+        let aapl = engine.mass_cancel(&MassCancelFilter::default().with_symbol("AAPL"));
+        assert_eq!(aapl, vec![ids[1], ids[3], ids[4]]);
+        assert!(engine.get_orderbook("AAPL").unwrap().asks.is_empty());
+        assert_eq!(engine.get_order(ids[3]).unwrap().status, OrderStatus::Cancelled);
+        assert!(engine.mass_cancel(&MassCancelFilter::default()).is_empty());
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_cancel_on_disconnect() {
+        let engine = engine();
+        engine.enable_cancel_on_disconnect("session-1", "mm");
+
+        let quote = limit_order(OrderSide::Buy, dec!(10), dec!(99.00), "mm");
+        let quote_id = quote.id;
+        engine.submit_order(quote).unwrap();
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(10), dec!(98.00), "other")).unwrap();
+
+        assert!(engine.disconnect("session-2").is_empty());
+        assert_eq!(engine.disconnect("session-1"), vec![quote_id]);
+        assert_eq!(engine.get_orderbook("AAPL").unwrap().best_bid(), Some(dec!(98.00)));
+
+        // The registration ends with the session
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(10), dec!(99.00), "mm")).unwrap();
+        assert!(engine.disconnect("session-1").is_empty());
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_oco_partial_fill_cancels_sibling() {
+        let engine = engine();
+
+        let take_profit = limit_order(OrderSide::Sell, dec!(100), dec!(110.00), "stopper");
+        let stop = stop_loss_order(OrderSide::Sell, dec!(100), dec!(95.00));
+        let (take_profit_id, stop_id) = (take_profit.id, stop.id);
+        let (group_id, trades) = engine.submit_oco(vec![take_profit, stop]).unwrap();
+        assert!(trades.is_empty());
+        assert_eq!(engine.get_group(group_id).unwrap().status, GroupStatus::Working);
+
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(40), dec!(110.00), "buyer")).unwrap();
+        assert_eq!(engine.get_order(stop_id).unwrap().status, OrderStatus::Cancelled);
+        assert_eq!(engine.get_group(group_id).unwrap().status, GroupStatus::Triggered);
+
+        engine.cancel_order(take_profit_id).unwrap();
+        assert_eq!(engine.get_group(group_id).unwrap().status, GroupStatus::Completed);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_oco_rejected_leg_cancels_accepted_legs() {
+        let engine = engine();
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(10), dec!(100.00), "buyer")).unwrap();
+
+        let first = limit_order(OrderSide::Sell, dec!(100), dec!(110.00), "stopper");
+        let crossing = limit_order(OrderSide::Sell, dec!(100), dec!(100.00), "stopper")
+            .with_post_only(PostOnly::Reject);
+        let first_id = first.id;
+        assert!(engine.submit_oco(vec![first, crossing]).is_err());
+
+        assert_eq!(engine.get_order(first_id).unwrap().status, OrderStatus::Cancelled);
+        assert!(engine.groups.is_empty());
+        assert!(engine.group_legs.is_empty());
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_bracket_lifecycle() {
+        let engine = engine();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(60), dec!(100.00), "seller")).unwrap();
+
+        let entry = limit_order(OrderSide::Buy, dec!(100), dec!(100.00), "stopper");
+        let take_profit = limit_order(OrderSide::Sell, dec!(100), dec!(110.00), "stopper");
+        let stop = stop_loss_order(OrderSide::Sell, dec!(100), dec!(95.00));
+        let (entry_id, take_profit_id, stop_id) = (entry.id, take_profit.id, stop.id);
+
+        // A partial entry fill keeps the exits held back
+        let (group_id, trades) = engine.submit_bracket(entry, take_profit, stop).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert!(engine.get_order(take_profit_id).is_none());
+
+        // Cancelling the rest of the entry releases exits for the 60 bought
+        engine.cancel_order(entry_id).unwrap();
+        let group = engine.get_group(group_id).unwrap();
+        assert_eq!(group.status, GroupStatus::Triggered);
+        assert_eq!(group.legs, vec![take_profit_id, stop_id]);
+        assert_eq!(engine.get_order(stop_id).unwrap().quantity, dec!(60));
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.depth(OrderSide::Sell, 1), vec![(dec!(110.00), dec!(60))]);
+
+        // A partial exit shrinks the stop to the 40 still held
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(20), dec!(110.00), "buyer")).unwrap();
+        let stop = engine.get_order(stop_id).unwrap();
+        assert_eq!((stop.status, stop.quantity), (OrderStatus::Pending, dec!(40)));
+
+        // Taking the rest of the profit cancels the stop
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(40), dec!(110.00), "buyer")).unwrap();
+        assert_eq!(engine.get_order(stop_id).unwrap().status, OrderStatus::Cancelled);
+        assert_eq!(engine.get_group(group_id).unwrap().status, GroupStatus::Completed);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_resting_execution_conditions_keep_priority() {
+        let engine = engine();
+        let all_or_none = limit_order(OrderSide::Sell, dec!(100), dec!(100.00), "aon").with_all_or_none();
+        let min_quantity = limit_order(OrderSide::Sell, dec!(80), dec!(100.00), "min").with_min_quantity(dec!(50));
+        let plain = limit_order(OrderSide::Sell, dec!(30), dec!(100.00), "plain");
+        let (all_or_none_id, min_quantity_id, plain_id) = (all_or_none.id, min_quantity.id, plain.id);
+        engine.submit_order(all_or_none).unwrap();
+        engine.submit_order(min_quantity).unwrap();
+        engine.submit_order(plain).unwrap();
+
+        // 40 is too little for the first two, so it goes to the order behind them
+        let taker = limit_order(OrderSide::Buy, dec!(40), dec!(100.00), "buyer").with_time_in_force(TimeInForce::Ioc);
+        let trades = engine.submit_order(taker).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!((trades[0].seller_order_id, trades[0].quantity), (plain_id, dec!(30)));
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.depth(OrderSide::Sell, 1), vec![(dec!(100.00), dec!(180))]);
+
+        // Skipped orders are still first in line once their conditions are met
+        let trades = engine
+            .submit_order(limit_order(OrderSide::Buy, dec!(160), dec!(100.00), "buyer"))
+            .unwrap();
+        let fills: Vec<(Uuid, Decimal)> = trades.iter().map(|t| (t.seller_order_id, t.quantity)).collect();
+        assert_eq!(fills, vec![(all_or_none_id, dec!(100)), (min_quantity_id, dec!(60))]);
+
+        // The last 20 are below the minimum, so the minimum shrinks to match
+        assert_eq!(engine.get_order(min_quantity_id).unwrap().min_execution(), dec!(20));
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.depth(OrderSide::Sell, 1), vec![(dec!(100.00), dec!(20))]);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_incoming_execution_conditions() {
+        let engine = engine();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(10), dec!(100.00), "small")).unwrap();
+        engine.submit_order(limit_order(OrderSide::Sell, dec!(50), dec!(100.01), "large")).unwrap();
+
+        // Each execution must be at least 20, so the 10 lot is passed over
+        let taker = limit_order(OrderSide::Buy, dec!(60), dec!(100.01), "buyer")
+            .with_min_quantity(dec!(20))
+            .with_time_in_force(TimeInForce::Ioc);
+        let trades = engine.submit_order(taker).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!((trades[0].price, trades[0].quantity), (dec!(100.01), dec!(50)));
+
+        // All-or-none takes nothing unless it can take everything
+        let taker = limit_order(OrderSide::Buy, dec!(20), dec!(100.00), "buyer")
+            .with_all_or_none()
+            .with_time_in_force(TimeInForce::Ioc);
+        let taker_id = taker.id;
+        assert!(engine.submit_order(taker).unwrap().is_empty());
+        assert_eq!(engine.get_order(taker_id).unwrap().status, OrderStatus::Cancelled);
+        assert_eq!(engine.get_orderbook("AAPL").unwrap().depth(OrderSide::Sell, 1)[0].1, dec!(10));
+
+        let taker = limit_order(OrderSide::Buy, dec!(10), dec!(100.00), "buyer").with_all_or_none();
+        assert_eq!(engine.submit_order(taker).unwrap().len(), 1);
+
+        let invalid = limit_order(OrderSide::Buy, dec!(10), dec!(100.00), "buyer").with_min_quantity(dec!(20));
+        assert!(engine.submit_order(invalid).is_err());
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_hidden_orders_trade_behind_lit() {
+        let engine = engine();
+        let hidden = limit_order(OrderSide::Sell, dec!(100), dec!(100.00), "dark").with_hidden();
+        let better = limit_order(OrderSide::Sell, dec!(30), dec!(99.99), "dark").with_hidden();
+        let lit = limit_order(OrderSide::Sell, dec!(50), dec!(100.00), "lit");
+        let (hidden_id, better_id, lit_id) = (hidden.id, better.id, lit.id);
+        engine.submit_order(hidden).unwrap();
+        engine.submit_order(better).unwrap();
+        engine.submit_order(lit).unwrap();
+
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.best_ask(), Some(dec!(100.00)));
+        assert_eq!(book.depth(OrderSide::Sell, 5), vec![(dec!(100.00), dec!(50))]);
+
+        // Price still comes first; at the same price the lit order goes ahead
+        let trades = engine
+            .submit_order(limit_order(OrderSide::Buy, dec!(100), dec!(100.00), "buyer"))
+            .unwrap();
+        let fills: Vec<(Uuid, Decimal)> = trades.iter().map(|t| (t.seller_order_id, t.quantity)).collect();
+        assert_eq!(fills, vec![(better_id, dec!(30)), (lit_id, dec!(50)), (hidden_id, dec!(20))]);
+
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert!(book.depth(OrderSide::Sell, 5).is_empty());
+        assert_eq!(book.best_ask(), None);
+        assert_eq!(book.level(OrderSide::Sell, dec!(100.00)).unwrap().dark_quantity, Qty::new(80));
+
+        let iceberg = limit_order(OrderSide::Sell, dec!(100), dec!(100.00), "dark")
+            .with_hidden()
+            .with_display_quantity(dec!(10));
+        assert!(engine.submit_order(iceberg).is_err());
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_mass_quote_replaces_both_sides() {
+        let engine = engine();
+        let acks = engine.mass_quote("maker", vec![
+            QuoteEntry::new("AAPL").with_bid(dec!(99.99), dec!(100)).with_ask(dec!(100.01), dec!(100)),
+            QuoteEntry::new("MSFT").with_bid(dec!(50.00), dec!(10)).with_ask(dec!(50.05), dec!(10)),
+        ]);
+        assert!(acks.iter().all(|ack| ack.status == QuoteStatus::Accepted));
+        let first = engine.get_quote("maker", "AAPL").unwrap();
+        assert_eq!(first.quote_id, acks[0].quote_id);
+
+        // Replacing pulls the old quote and sells into the resting bid
+        engine.submit_order(limit_order(OrderSide::Buy, dec!(30), dec!(100.00), "buyer")).unwrap();
+        let acks = engine.mass_quote("maker", vec![
+            QuoteEntry::new("AAPL").with_bid(dec!(99.90), dec!(50)).with_ask(dec!(100.00), dec!(100)),
+            QuoteEntry::new("MSFT").with_bid(dec!(50.05), dec!(10)).with_ask(dec!(50.05), dec!(10)),
+            QuoteEntry::new("XYZ").with_bid(dec!(1.00), dec!(10)),
+        ]);
+        assert_eq!(acks[0].status, QuoteStatus::Accepted);
+        assert_eq!(acks[0].trades.len(), 1);
+        assert_eq!(acks[0].trades[0].quantity, dec!(30));
+        assert!(matches!(acks[1].status, QuoteStatus::Rejected(_)));
+        assert_eq!(acks[2].status, QuoteStatus::Rejected("Unknown symbol XYZ".to_string()));
+
+        let bid_id = first.bid_order_id.unwrap();
+        assert_eq!(engine.get_order(bid_id).unwrap().status, OrderStatus::Cancelled);
+        let book = engine.get_orderbook("AAPL").unwrap();
+        assert_eq!(book.depth(OrderSide::Buy, 5), vec![(dec!(99.90), dec!(50))]);
+        assert_eq!(book.depth(OrderSide::Sell, 5), vec![(dec!(100.00), dec!(70))]);
+
+        // The rejected MSFT replace left the first quote working
+        let book = engine.get_orderbook("MSFT").unwrap();
+        assert_eq!(book.depth(OrderSide::Buy, 5), vec![(dec!(50.00), dec!(10))]);
+
+        assert_consistent(&engine);
+    }
+
+    #[test]
+    fn test_mass_cancel_quotes() {
+        let engine = engine();
+        engine.mass_quote("maker", vec![
+            QuoteEntry::new("AAPL").with_bid(dec!(99.99), dec!(100)).with_ask(dec!(100.01), dec!(100)),
+            QuoteEntry::new("MSFT").with_bid(dec!(50.00), dec!(10)).with_ask(dec!(50.05), dec!(10)),
+        ]);
+        let order = limit_order(OrderSide::Buy, dec!(10), dec!(99.00), "maker");
+        let order_id = order.id;
         engine.submit_order(order).unwrap();
-        
-        assert!(engine.cancel_order(order_id).is_ok());
-        
-        let cancelled_order = engine.get_order(order_id).unwrap();
-        assert_eq!(cancelled_order.status, OrderStatus::Cancelled);
+
+        // Pulling one side keeps the quote alive on the other
+        let filter = MassCancelFilter::default().with_user("maker").with_side(OrderSide::Sell);
+        assert_eq!(engine.mass_cancel_quotes(&filter.with_symbol("MSFT")).len(), 1);
+        assert!(engine.get_quote("maker", "MSFT").is_some());
+
+        let cancelled = engine.mass_cancel_quotes(&MassCancelFilter::default().with_user("maker"));
+        assert_eq!(cancelled.len(), 3);
+        assert!(engine.get_quote("maker", "AAPL").is_none());
+        assert!(engine.get_quote("maker", "MSFT").is_none());
+        assert!(engine.get_order(order_id).unwrap().is_active());
+
+        assert_consistent(&engine);
     }
 }
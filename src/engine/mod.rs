@@ -1,3 +1,17 @@
+pub mod algorithms;
+pub mod dispatcher;
+pub mod events;
+pub mod mass_cancel;
+pub mod mass_quote;
+pub mod matcher;
 pub mod matching_engine;
+pub mod router;
 
+pub use algorithms::{LmmSplit, MatchingAlgorithm, PriceTime, ProRata, RestingOrder, TopOrderProRata};
+pub use dispatcher::{Command, CommandResult, OrderEntry, SymbolDispatcher};
+pub use events::EngineEvent;
+pub use mass_cancel::MassCancelFilter;
+pub use mass_quote::{MakerQuote, QuoteAck, QuoteEntry, QuoteStatus};
+pub use matcher::SimulationResult;
 pub use matching_engine::MatchingEngine;
+pub use router::{ShardRouter, SymbolState};
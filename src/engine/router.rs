@@ -0,0 +1,605 @@
+use crossbeam::channel::{bounded, unbounded, Sender};
+use dashmap::DashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::JoinHandle;
+use uuid::Uuid;
+
+use super::dispatcher::{apply, Command, CommandResult, OrderEntry};
+use super::{EngineEvent, MakerQuote, MatchingAlgorithm, MatchingEngine};
+use crate::models::{Instrument, Order, OrderBook, OrderGroup, PriceBands, SessionState, StopBook, Timetable};
+
+/// Everything an engine holds for one symbol, as moved between engines by
+/// `MatchingEngine::detach_symbol` and `MatchingEngine::attach_symbol`.
+pub struct SymbolState {
+    pub(crate) instrument: Instrument,
+    pub(crate) book: Option<OrderBook>,
+    pub(crate) stops: Option<StopBook>,
+    pub(crate) algorithm: Option<Arc<dyn MatchingAlgorithm>>,
+    pub(crate) session: Option<SessionState>,
+    pub(crate) price_bands: Option<PriceBands>,
+    pub(crate) timetable: Option<(Timetable, Option<SessionState>)>,
+    /// The symbol's orders, working or finished
+    pub(crate) orders: Vec<Order>,
+    pub(crate) quotes: Vec<MakerQuote>,
+    /// Order groups whose orders are all on the symbol
+    pub(crate) groups: Vec<OrderGroup>,
+    pub(crate) group_legs: Vec<(Uuid, Uuid)>,
+    /// Cancel-on-disconnect sessions of users with orders on the symbol
+    pub(crate) sessions: Vec<(String, String)>,
+}
+
+impl SymbolState {
+    pub fn symbol(&self) -> &str {
+        &self.instrument.symbol
+    }
+}
+
+type Job = Box<dyn FnOnce(&MatchingEngine) + Send>;
+
+/// The shard a symbol is on. Commands for the symbol hold it for reading
+/// while they run, so a migration takes it for writing to wait for them and
+/// hold back new ones without pausing any other symbol.
+type Route = Arc<RwLock<usize>>;
+
+/// Partitions symbols across shards, each an engine of its own driven by a
+/// single worker thread. Every order command for a symbol runs on its
+/// shard's thread in arrival order, while symbols on different shards are
+/// processed in parallel. Reads go straight to the owning engine.
+pub struct ShardRouter {
+    engines: Vec<Arc<MatchingEngine>>,
+    /// Job queue of each shard; emptied on shutdown
+    lanes: RwLock<Vec<Sender<Job>>>,
+    /// Symbols placed explicitly, moved or registered here; any other
+    /// symbol is hashed
+    routes: RwLock<HashMap<String, Route>>,
+    /// Symbol of each order routed here while it is working. Orders that
+    /// stop working as a side effect of another symbol's command are
+    /// dropped the next time they are looked up.
+    working: DashMap<Uuid, String>,
+    /// Migrations begun and completed, so a lookup that asks every shard
+    /// can tell whether an order may have been between two of them
+    migrations_started: AtomicU64,
+    migrations_finished: AtomicU64,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl ShardRouter {
+    /// A router over `shards` fresh engines.
+    pub fn new(shards: usize) -> Self {
+        Self::from_engines((0..shards.max(1)).map(|_| MatchingEngine::new()).collect())
+    }
+
+    /// A router with one shard per engine, for engines that need their own
+    /// configuration.
+    pub fn from_engines(engines: Vec<MatchingEngine>) -> Self {
+        assert!(!engines.is_empty(), "a router needs at least one shard");
+
+        let engines: Vec<Arc<MatchingEngine>> = engines.into_iter().map(Arc::new).collect();
+        let mut lanes = Vec::new();
+        let mut workers = Vec::new();
+
+        for (index, engine) in engines.iter().enumerate() {
+            let (tx, rx) = unbounded::<Job>();
+            let engine = Arc::clone(engine);
+
+            let worker = std::thread::Builder::new()
+                .name(format!("shard-{}", index))
+                .spawn(move || {
+                    for job in rx {
+                        job(&engine);
+                    }
+                })
+                .expect("failed to spawn shard thread");
+
+            lanes.push(tx);
+            workers.push(worker);
+        }
+
+        Self {
+            engines,
+            lanes: RwLock::new(lanes),
+            routes: RwLock::new(HashMap::new()),
+            working: DashMap::new(),
+            migrations_started: AtomicU64::new(0),
+            migrations_finished: AtomicU64::new(0),
+            workers: Mutex::new(workers),
+        }
+    }
+
+    /// Places `symbol` on `shard` instead of the one its hash picks.
+    pub fn with_assignment(self, symbol: &str, shard: usize) -> Self {
+        assert!(shard < self.engines.len(), "no shard {} among {}", shard, self.engines.len());
+        self.routes
+            .write()
+            .unwrap()
+            .insert(symbol.to_string(), Arc::new(RwLock::new(shard)));
+        self
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.engines.len()
+    }
+
+    /// The shard that currently owns `symbol`.
+    pub fn shard_of(&self, symbol: &str) -> usize {
+        match self.known_route(symbol) {
+            Some(route) => *route.read().unwrap(),
+            None => self.hashed_shard(symbol),
+        }
+    }
+
+    /// The engine behind `shard`, for reads and anything the router does
+    /// not route itself.
+    pub fn shard(&self, shard: usize) -> Option<&MatchingEngine> {
+        self.engines.get(shard).map(|engine| engine.as_ref())
+    }
+
+    pub fn engine_for(&self, symbol: &str) -> &MatchingEngine {
+        &self.engines[self.shard_of(symbol)]
+    }
+
+    pub fn register_instrument(&self, instrument: Instrument) -> Result<(), String> {
+        let route = self.route(&instrument.symbol);
+        self.run_on(&route, move |engine| engine.register_instrument(instrument))
+    }
+
+    /// Finds a working order through the index and anything else by asking
+    /// every shard.
+    pub fn get_order(&self, order_id: Uuid) -> Option<Order> {
+        if let Some(symbol) = self.working.get(&order_id).map(|symbol| symbol.clone()) {
+            let order = self.read(&symbol, |engine| engine.get_order(order_id));
+            if !order.as_ref().is_some_and(|order| order.is_active()) {
+                self.working.remove(&order_id);
+            }
+            if order.is_some() {
+                return order;
+            }
+        }
+
+        // A miss only counts if no symbol was on the move while looking
+        loop {
+            let started = self.migrations_started.load(Ordering::SeqCst);
+            if started == self.migrations_finished.load(Ordering::SeqCst) {
+                let order = self.engines.iter().find_map(|engine| engine.get_order(order_id));
+                if order.is_some() || self.migrations_started.load(Ordering::SeqCst) == started {
+                    return order;
+                }
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    pub fn get_orderbook(&self, symbol: &str) -> Option<OrderBook> {
+        self.read(symbol, |engine| engine.get_orderbook(symbol))
+    }
+
+    /// Drains the events of every shard. Events keep their order within a
+    /// shard, and so within a symbol.
+    pub fn drain_events(&self) -> Vec<EngineEvent> {
+        self.engines.iter().flat_map(|engine| engine.drain_events()).collect()
+    }
+
+    /// Moves `symbol` to `shard` with its book, working orders, trading
+    /// state and order history. Only commands for `symbol` are held back
+    /// while it moves. The symbol is detached on its shard's thread once
+    /// everything already queued there has been applied, and attached on the
+    /// new shard's thread the same way; see `MatchingEngine::detach_symbol`
+    /// for when it can move. If the new shard cannot take it, it goes back
+    /// where it was.
+    pub fn migrate_symbol(&self, symbol: &str, shard: usize) -> Result<(), String> {
+        let destination = self
+            .engines
+            .get(shard)
+            .ok_or_else(|| format!("No shard {} among {}", shard, self.engines.len()))?;
+
+        let route = self.route(symbol);
+        let mut current = route.write().unwrap();
+        let source = *current;
+        if source == shard {
+            return Ok(());
+        }
+        if destination.get_instrument(symbol).is_some() {
+            return Err(format!("Shard {} already trades {}", shard, symbol));
+        }
+
+        self.migrations_started.fetch_add(1, Ordering::SeqCst);
+        let moved = self.move_symbol(symbol, source, shard);
+        self.migrations_finished.fetch_add(1, Ordering::SeqCst);
+
+        moved?;
+        *current = shard;
+        Ok(())
+    }
+
+    fn move_symbol(&self, symbol: &str, source: usize, shard: usize) -> Result<(), String> {
+        let owned = symbol.to_string();
+        let detached = self.send(source, move |engine| {
+            engine.get_instrument(&owned).map(|_| engine.detach_symbol(&owned))
+        })?;
+
+        // A symbol with nothing registered yet only needs its placement changed
+        if let Some(state) = detached.transpose()? {
+            let attached = self.send(shard, move |engine| engine.attach_symbol(state))?;
+            if let Err((e, state)) = attached {
+                // Hand the symbol back rather than leave no shard trading it
+                self.send(source, move |engine| engine.attach_symbol(*state))?
+                    .map_err(|(lost, _)| format!("{} could not be moved or restored: {}; {}", symbol, e, lost))?;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn hashed_shard(&self, symbol: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        symbol.hash(&mut hasher);
+        (hasher.finish() % self.engines.len() as u64) as usize
+    }
+
+    /// The route of `symbol`, placing it by its hash if it has none yet.
+    fn route(&self, symbol: &str) -> Route {
+        if let Some(route) = self.routes.read().unwrap().get(symbol) {
+            return Arc::clone(route);
+        }
+        let shard = self.hashed_shard(symbol);
+        let mut routes = self.routes.write().unwrap();
+        Arc::clone(routes.entry(symbol.to_string()).or_insert_with(|| Arc::new(RwLock::new(shard))))
+    }
+
+    /// The route of `symbol` if it has one or its hashed shard trades it.
+    /// Symbols nobody trades get no route, so unknown symbols do not pile up.
+    fn known_route(&self, symbol: &str) -> Option<Route> {
+        if let Some(route) = self.routes.read().unwrap().get(symbol) {
+            return Some(Arc::clone(route));
+        }
+        self.engines[self.hashed_shard(symbol)]
+            .get_instrument(symbol)
+            .map(|_| self.route(symbol))
+    }
+
+    /// Reads from the engine that owns `symbol`, never while it is moving.
+    fn read<T>(&self, symbol: &str, read: impl FnOnce(&MatchingEngine) -> T) -> T {
+        match self.known_route(symbol) {
+            Some(route) => read(&self.engines[*route.read().unwrap()]),
+            None => read(&self.engines[self.hashed_shard(symbol)]),
+        }
+    }
+
+    /// Runs `job` on the shard that owns `symbol`, after every job already
+    /// queued there, and waits for its result.
+    fn run<T, F>(&self, symbol: &str, job: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&MatchingEngine) -> T + Send + 'static,
+    {
+        match self.known_route(symbol) {
+            Some(route) => self.run_on(&route, job),
+            None => self.send(self.hashed_shard(symbol), job),
+        }
+    }
+
+    fn run_on<T, F>(&self, route: &Route, job: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&MatchingEngine) -> T + Send + 'static,
+    {
+        // Holding the route until the job is done keeps a migration from
+        // moving the symbol while it is queued or running
+        let shard = route.read().unwrap();
+        self.send(*shard, job)
+    }
+
+    /// Queues `job` on `shard` and waits for its result. A job that panics
+    /// fails with an error and the shard carries on.
+    fn send<T, F>(&self, shard: usize, job: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&MatchingEngine) -> T + Send + 'static,
+    {
+        let lane = self.lanes.read().unwrap().get(shard).cloned().ok_or("Router has stopped")?;
+        let (reply_tx, reply_rx) = bounded(1);
+
+        lane.send(Box::new(move |engine: &MatchingEngine| {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| job(engine)));
+            // The caller may have given up waiting
+            let _ = reply_tx.send(result);
+        }))
+        .map_err(|_| format!("Shard {} has stopped", shard))?;
+        reply_rx
+            .recv()
+            .map_err(|_| format!("Shard {} has stopped", shard))?
+            .map_err(|_| format!("Job panicked on shard {}", shard))
+    }
+
+    /// Stops accepting commands and waits for every queued command to be
+    /// applied.
+    pub fn shutdown(&self) {
+        self.lanes.write().unwrap().clear();
+        for worker in self.workers.lock().unwrap().drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl OrderEntry for ShardRouter {
+    /// Applies `command` on the shard that owns `symbol` and waits for the
+    /// result, keeping the index of working orders up to date with the
+    /// orders it touched.
+    fn execute(&self, symbol: &str, command: Command) -> Result<CommandResult, String> {
+        let mut touched = match &command {
+            Command::Submit(order) => vec![order.id],
+            Command::Cancel(order_id) | Command::Amend { order_id, .. } => vec![*order_id],
+            Command::Replace { order_id, replacement } => vec![*order_id, replacement.id],
+        };
+
+        let (result, working) = self.run(symbol, move |engine| {
+            let result = apply(engine, command);
+            if let CommandResult::Submitted(Ok(trades))
+            | CommandResult::Amended(Ok(trades))
+            | CommandResult::Replaced(Ok(trades)) = &result
+            {
+                touched.extend(trades.iter().flat_map(|trade| [trade.buyer_order_id, trade.seller_order_id]));
+            }
+
+            let working: Vec<(Uuid, Option<String>)> = touched
+                .into_iter()
+                .map(|order_id| {
+                    let order = engine.get_order(order_id).filter(|order| order.is_active());
+                    (order_id, order.map(|order| order.symbol))
+                })
+                .collect();
+            (result, working)
+        })?;
+
+        for (order_id, symbol) in working {
+            match symbol {
+                Some(symbol) => {
+                    self.working.insert(order_id, symbol);
+                }
+                None => {
+                    self.working.remove(&order_id);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn symbol_of(&self, order_id: Uuid) -> Result<String, String> {
+        if let Some(symbol) = self.working.get(&order_id) {
+            return Ok(symbol.clone());
+        }
+        self.get_order(order_id)
+            .map(|order| order.symbol)
+            .ok_or_else(|| "Order not found".to_string())
+    }
+}
+
+impl Drop for ShardRouter {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{AuctionKind, GroupStatus, OrderSide, OrderStatus, OrderType};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    const SYMBOLS: [&str; 4] = ["AAPL", "MSFT", "GOOG", "AMZN"];
+
+    fn router() -> ShardRouter {
+        let router = ShardRouter::new(2)
+            .with_assignment("AAPL", 0)
+            .with_assignment("MSFT", 1)
+            .with_assignment("GOOG", 0)
+            .with_assignment("AMZN", 1);
+        for symbol in SYMBOLS {
+            router.register_instrument(Instrument::new(symbol, dec!(0.01), "USD")).unwrap();
+        }
+        router
+    }
+
+    fn limit_order(symbol: &str, side: OrderSide, quantity: Decimal, price: Decimal, user: &str) -> Order {
+        Order::new(
+            symbol.to_string(),
+            side,
+            OrderType::Limit,
+            quantity,
+            Some(price),
+            None,
+            user.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_symbols_trade_on_their_own_shard() {
+        let router = router();
+        assert_eq!((router.shard_of("AAPL"), router.shard_of("MSFT")), (0, 1));
+
+        let ask = limit_order("MSFT", OrderSide::Sell, dec!(100), dec!(300.00), "seller");
+        let ask_id = ask.id;
+        router.submit_order(ask).unwrap();
+        router.submit_order(limit_order("AAPL", OrderSide::Sell, dec!(50), dec!(150.00), "seller")).unwrap();
+
+        // Each shard only knows its own symbols
+        assert!(router.shard(0).unwrap().get_orderbook("MSFT").is_none());
+        assert_eq!(router.shard(1).unwrap().get_order(ask_id).unwrap().price, Some(dec!(300.00)));
+
+        router.amend_order(ask_id, Some(dec!(60)), None).unwrap();
+        let trades = router
+            .submit_order(limit_order("MSFT", OrderSide::Buy, dec!(100), dec!(300.00), "buyer"))
+            .unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, dec!(60));
+        assert_eq!(router.get_order(ask_id).unwrap().status, OrderStatus::Filled);
+        assert_eq!(router.cancel_order(Uuid::new_v4()).unwrap_err(), "Order not found");
+
+        // Unpinned symbols are hashed onto a shard and stay there
+        let shard = router.shard_of("TSLA");
+        assert!(shard < router.shard_count());
+        assert_eq!(router.shard_of("TSLA"), shard);
+        let unknown = limit_order("TSLA", OrderSide::Buy, dec!(1), dec!(1.00), "buyer");
+        assert_eq!(router.submit_order(unknown).unwrap_err(), "Unknown symbol TSLA");
+    }
+
+    #[test]
+    fn test_migrate_symbol_with_working_orders() {
+        let router = router();
+        let resting = limit_order("AAPL", OrderSide::Sell, dec!(100), dec!(150.00), "seller");
+        let resting_id = resting.id;
+        router.submit_order(resting).unwrap();
+
+        router.engine_for("AAPL").start_auction("AAPL", AuctionKind::Opening);
+        assert_eq!(router.migrate_symbol("AAPL", 1).unwrap_err(), "AAPL is in an auction call");
+        router.engine_for("AAPL").uncross_auction("AAPL").unwrap();
+
+        // The resting order moves with its book
+        router.migrate_symbol("AAPL", 1).unwrap();
+        assert_eq!(router.shard_of("AAPL"), 1);
+        assert!(router.shard(0).unwrap().get_instrument("AAPL").is_none());
+        assert!(router.shard(1).unwrap().get_order(resting_id).unwrap().is_active());
+        assert_eq!(router.get_orderbook("AAPL").unwrap().best_ask(), Some(dec!(150.00)));
+
+        // Trading carries on at the new shard, next to its other symbols
+        let trades = router
+            .submit_order(limit_order("AAPL", OrderSide::Buy, dec!(100), dec!(150.00), "buyer"))
+            .unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(router.get_order(resting_id).unwrap().status, OrderStatus::Filled);
+        assert_eq!(router.shard(1).unwrap().get_orderbook("AAPL").unwrap().last_trade_price, Some(dec!(150.00)));
+
+        assert!(router.migrate_symbol("AAPL", 2).is_err());
+    }
+
+    #[test]
+    fn test_migration_carries_groups_and_sessions() {
+        let router = router();
+        let origin = router.shard(0).unwrap();
+
+        // A live group spanning two symbols pins both of them until it is done
+        let legs = vec![
+            limit_order("AAPL", OrderSide::Sell, dec!(10), dec!(160.00), "trader"),
+            limit_order("GOOG", OrderSide::Sell, dec!(10), dec!(2900.00), "trader"),
+        ];
+        let leg_ids: Vec<Uuid> = legs.iter().map(|leg| leg.id).collect();
+        origin.submit_oco(legs).unwrap();
+        assert_eq!(router.migrate_symbol("AAPL", 1).unwrap_err(), "AAPL shares an order group with another symbol");
+        router.cancel_order(leg_ids[0]).unwrap();
+        assert_eq!(router.get_order(leg_ids[1]).unwrap().status, OrderStatus::Cancelled);
+        router.migrate_symbol("AAPL", 1).unwrap();
+
+        let take_profit = limit_order("MSFT", OrderSide::Sell, dec!(100), dec!(310.00), "trader");
+        let other = limit_order("MSFT", OrderSide::Sell, dec!(100), dec!(320.00), "trader");
+        let (take_profit_id, other_id) = (take_profit.id, other.id);
+        let (group_id, _) = router.shard(1).unwrap().submit_oco(vec![take_profit, other]).unwrap();
+
+        let bid = limit_order("MSFT", OrderSide::Buy, dec!(10), dec!(290.00), "mm");
+        let bid_id = bid.id;
+        router.shard(1).unwrap().enable_cancel_on_disconnect("session-1", "mm");
+        router.submit_order(bid).unwrap();
+
+        router.migrate_symbol("MSFT", 0).unwrap();
+        let target = router.shard(0).unwrap();
+        assert!(router.shard(1).unwrap().get_group(group_id).is_none());
+
+        // The group keeps working on the new shard: a fill on one leg cancels the other
+        router
+            .submit_order(limit_order("MSFT", OrderSide::Buy, dec!(40), dec!(310.00), "buyer"))
+            .unwrap();
+        assert_eq!(router.get_order(other_id).unwrap().status, OrderStatus::Cancelled);
+        assert_eq!(target.get_group(group_id).unwrap().status, GroupStatus::Triggered);
+        assert!(router.get_order(take_profit_id).unwrap().is_active());
+
+        // And so does the session, for the orders that moved
+        assert_eq!(target.disconnect("session-1"), vec![bid_id]);
+        assert_eq!(router.get_order(bid_id).unwrap().status, OrderStatus::Cancelled);
+    }
+
+    #[test]
+    fn test_failed_attach_hands_the_symbol_back() {
+        let router = router();
+        router.submit_order(limit_order("AAPL", OrderSide::Sell, dec!(10), dec!(150.00), "seller")).unwrap();
+        let buy = limit_order("AAPL", OrderSide::Buy, dec!(10), dec!(150.00), "buyer");
+        let buy_id = buy.id;
+        router.submit_order(buy).unwrap();
+
+        // Shard 1 starts trading AAPL on its own, so the symbol cannot move there
+        let (origin, other) = (router.shard(0).unwrap(), router.shard(1).unwrap());
+        other.register_instrument(Instrument::new("AAPL", dec!(0.01), "USD"));
+        assert_eq!(router.migrate_symbol("AAPL", 1).unwrap_err(), "Shard 1 already trades AAPL");
+
+        let state = origin.detach_symbol("AAPL").unwrap();
+        let (error, state) = other.attach_symbol(state).unwrap_err();
+        assert_eq!(error, "AAPL is already registered");
+        assert!(origin.attach_symbol(*state).is_ok());
+
+        assert_eq!(router.shard_of("AAPL"), 0);
+        assert_eq!(origin.get_order(buy_id).unwrap().status, OrderStatus::Filled);
+        assert_eq!(router.get_orderbook("AAPL").unwrap().last_trade_price, Some(dec!(150.00)));
+    }
+
+    /// Crosses `count` pairs of orders on `symbol`, leaving every fifth buy
+    /// resting and cancelling it again.
+    fn trade(router: &ShardRouter, symbol: &str, count: usize) {
+        for i in 0..count {
+            let price = dec!(100.00) + Decimal::new((i % 7) as i64, 2);
+            let quantity = Decimal::from(1 + i % 4);
+            router
+                .submit_order(limit_order(symbol, OrderSide::Sell, quantity, price, "seller"))
+                .unwrap();
+
+            let buy = limit_order(symbol, OrderSide::Buy, quantity, price, "buyer");
+            let buy_id = buy.id;
+            router.submit_order(buy).unwrap();
+            if i % 5 == 0 {
+                let rest = limit_order(symbol, OrderSide::Buy, quantity, dec!(99.00), "buyer");
+                let rest_id = rest.id;
+                router.submit_order(rest).unwrap();
+                router.cancel_order(rest_id).unwrap();
+            }
+            assert_eq!(router.get_order(buy_id).unwrap().status, OrderStatus::Filled);
+        }
+    }
+
+    #[test]
+    fn test_concurrent_symbols_and_migration() {
+        let router = router();
+        let per_symbol = 300;
+
+        std::thread::scope(|scope| {
+            for symbol in SYMBOLS {
+                let router = &router;
+                scope.spawn(move || trade(router, symbol, per_symbol));
+            }
+
+            // GOOG is moved back and forth while every symbol keeps trading,
+            // holding up only its own commands
+            let router = &router;
+            scope.spawn(move || {
+                for i in 0..20 {
+                    router.migrate_symbol("GOOG", (i + 1) % 2).unwrap();
+                }
+            });
+        });
+
+        for symbol in SYMBOLS {
+            let book = router.get_orderbook(symbol).unwrap();
+            book.verify_invariants().unwrap();
+            assert!(book.is_empty(), "{} still has resting orders", symbol);
+            assert!(book.last_trade_price.is_some());
+
+            // Only the owning shard knows the symbol
+            let owners = (0..router.shard_count())
+                .filter(|&shard| router.shard(shard).unwrap().get_instrument(symbol).is_some())
+                .count();
+            assert_eq!(owners, 1);
+        }
+    }
+}
@@ -2,6 +2,15 @@ pub mod engine;
 pub mod models;
 pub mod risk;
 
-pub use engine::MatchingEngine;
-pub use models::{Order, OrderBook, OrderSide, OrderStatus, OrderType, Trade};
+pub use engine::{
+    EngineEvent, LmmSplit, MakerQuote, MassCancelFilter, MatchingAlgorithm, MatchingEngine, OrderEntry,
+    PriceTime, ProRata, QuoteAck, QuoteEntry, QuoteStatus, RestingOrder, ShardRouter, SimulationResult,
+    SymbolDispatcher, SymbolState, TopOrderProRata,
+};
+pub use models::{
+    AuctionKind, AuctionSummary, BandAction, BandBreach, GroupKind, GroupStatus, Instrument, Order,
+    OrderBook, OrderGroup, OrderSide, OrderStatus, OrderType, PegType, PostOnly, Price, PriceBands, Qty,
+    Rounding, SelfTradePrevention, SessionState, StopBook, TimeInForce, Timetable, Trade, TrailReference,
+    TrailingOffset, Uncross,
+};
 pub use risk::{RiskLimits, RiskManager};
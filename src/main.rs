@@ -1,13 +1,8 @@
-mod engine;
-mod models;
-mod risk;
-
-use engine::MatchingEngine;
-use models::{Order, OrderSide, OrderType};
-use risk::{RiskLimits, RiskManager};
 use rust_decimal_macros::dec;
+use rust_hft_trading_engine::{
+    Instrument, MatchingEngine, Order, OrderSide, OrderType, RiskLimits, RiskManager,
+};
 use tracing::{info, Level};
-use tracing_subscriber;
 
 #[tokio::main]
 async fn main() {
@@ -20,6 +15,7 @@ async fn main() {
 
     // Initialize components
     let engine = MatchingEngine::new();
+    engine.register_instrument(Instrument::new("AAPL", dec!(0.01), "USD"));
     let risk_manager = RiskManager::new(RiskLimits::default());
 
     info!("Components initialized successfully");
@@ -0,0 +1,128 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::OrderSide;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuctionKind {
+    Opening,
+    Closing,
+}
+
+/// The price at which a call auction uncrosses and what trades there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Uncross {
+    pub price: Decimal,
+    pub volume: Decimal,
+    /// Interest left unmatched at `price` on the heavier side
+    pub imbalance: Decimal,
+    pub imbalance_side: Option<OrderSide>,
+}
+
+impl Uncross {
+    /// Finds the uncrossing price for the given buy and sell interest, keyed
+    /// by limit price. Only limit prices are candidates. The price that
+    /// executes the most volume wins, then the one leaving the smallest
+    /// imbalance, then the one closest to `reference`, then the lowest.
+    pub fn compute(
+        bids: &BTreeMap<Decimal, Decimal>,
+        asks: &BTreeMap<Decimal, Decimal>,
+        reference: Option<Decimal>,
+    ) -> Option<Self> {
+        let mut candidates: Vec<Decimal> = bids.keys().chain(asks.keys()).copied().collect();
+        candidates.sort();
+        candidates.dedup();
+
+        let mut best: Option<Self> = None;
+        for price in candidates {
+            let demand: Decimal = bids.range(price..).map(|(_, quantity)| quantity).sum();
+            let supply: Decimal = asks.range(..=price).map(|(_, quantity)| quantity).sum();
+
+            let volume = demand.min(supply);
+            if volume <= Decimal::ZERO {
+                continue;
+            }
+
+            let candidate = Self {
+                price,
+                volume,
+                imbalance: (demand - supply).abs(),
+                imbalance_side: match demand.cmp(&supply) {
+                    std::cmp::Ordering::Greater => Some(OrderSide::Buy),
+                    std::cmp::Ordering::Less => Some(OrderSide::Sell),
+                    std::cmp::Ordering::Equal => None,
+                },
+            };
+
+            let better = match best {
+                None => true,
+                Some(best) => {
+                    let distance = |price: Decimal| reference.map(|reference| (price - reference).abs());
+                    // Candidates come in ascending price, so ties keep the lower price
+                    (candidate.volume, -candidate.imbalance) > (best.volume, -best.imbalance)
+                        || ((candidate.volume, candidate.imbalance) == (best.volume, best.imbalance)
+                            && distance(candidate.price) < distance(best.price))
+                }
+            };
+
+            if better {
+                best = Some(candidate);
+            }
+        }
+
+        best
+    }
+}
+
+/// Published when a call auction ends.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuctionSummary {
+    pub symbol: String,
+    pub kind: AuctionKind,
+    /// `None` when no orders crossed
+    pub uncross: Option<Uncross>,
+    pub trade_count: usize,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn interest(levels: &[(Decimal, Decimal)]) -> BTreeMap<Decimal, Decimal> {
+        levels.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_uncross_maximizes_volume_then_minimizes_imbalance() {
+        let bids = interest(&[(dec!(10.10), dec!(100)), (dec!(10.00), dec!(20))]);
+        let asks = interest(&[(dec!(10.00), dec!(100))]);
+
+        // Both prices execute 100, but 10.10 leaves no imbalance
+        let uncross = Uncross::compute(&bids, &asks, None).unwrap();
+        assert_eq!(uncross.price, dec!(10.10));
+        assert_eq!(uncross.volume, dec!(100));
+        assert_eq!(uncross.imbalance_side, None);
+
+        let asks = interest(&[(dec!(10.00), dec!(150))]);
+        let uncross = Uncross::compute(&bids, &asks, None).unwrap();
+        assert_eq!(uncross.price, dec!(10.00));
+        assert_eq!(uncross.volume, dec!(120));
+        assert_eq!((uncross.imbalance, uncross.imbalance_side), (dec!(30), Some(OrderSide::Sell)));
+
+        assert!(Uncross::compute(&interest(&[(dec!(9.90), dec!(10))]), &asks, None).is_none());
+    }
+
+    #[test]
+    fn test_uncross_uses_reference_price() {
+        let bids = interest(&[(dec!(10.10), dec!(100))]);
+        let asks = interest(&[(dec!(10.00), dec!(100))]);
+
+        assert_eq!(Uncross::compute(&bids, &asks, None).unwrap().price, dec!(10.00));
+        assert_eq!(Uncross::compute(&bids, &asks, Some(dec!(10.08))).unwrap().price, dec!(10.10));
+        assert_eq!(Uncross::compute(&bids, &asks, Some(dec!(9.50))).unwrap().price, dec!(10.00));
+    }
+}
@@ -0,0 +1,253 @@
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
+/// How a decimal value that falls between two points of a grid is brought
+/// onto it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rounding {
+    /// Values off the grid are an error
+    Exact,
+    /// Toward negative infinity
+    Down,
+    /// Toward positive infinity
+    Up,
+}
+
+/// Converts `value` to a whole number of `unit`s.
+fn to_units(value: Decimal, unit: Decimal, rounding: Rounding) -> Result<i64, String> {
+    if unit <= Decimal::ZERO {
+        return Err(format!("Unit {} must be positive", unit));
+    }
+
+    let overflow = || format!("{} in units of {} overflows", value, unit);
+    let units = value.checked_div(unit).ok_or_else(overflow)?;
+    let units = match rounding {
+        Rounding::Exact if !units.fract().is_zero() => {
+            return Err(format!("{} is not a multiple of {}", value, unit));
+        }
+        Rounding::Exact => units,
+        Rounding::Down => units.floor(),
+        Rounding::Up => units.ceil(),
+    };
+    units.to_i64().ok_or_else(overflow)
+}
+
+fn to_decimal(units: i64, unit: Decimal) -> Decimal {
+    Decimal::from(units)
+        .checked_mul(unit)
+        .unwrap_or_else(|| panic!("{} units of {} overflow a decimal", units, unit))
+}
+
+/// Grid used when reference data sets no tick or lot size.
+pub const DEFAULT_UNIT: Decimal = Decimal::from_parts(1, 0, 0, false, 8);
+
+/// Price unit for a book whose prices are multiples of `ticks`: half the
+/// coarsest unit every tick is a multiple of, so the midpoint of any two
+/// valid prices is exact as well.
+pub fn price_grid(ticks: impl IntoIterator<Item = Decimal>) -> Decimal {
+    let mut ticks = ticks.into_iter().filter(|tick| *tick > Decimal::ZERO);
+    ticks
+        .next()
+        .and_then(|first| ticks.try_fold(first, common_unit))
+        .map_or(DEFAULT_UNIT, |unit| unit / Decimal::TWO)
+}
+
+/// The coarsest unit both `a` and `b` are whole multiples of, if the two
+/// are positive and their digits fit in an `i128`.
+pub fn common_unit(a: Decimal, b: Decimal) -> Option<Decimal> {
+    if a <= Decimal::ZERO || b <= Decimal::ZERO {
+        return None;
+    }
+
+    let (a, b) = (a.normalize(), b.normalize());
+    let scale = a.scale().max(b.scale());
+    let digits = |value: Decimal| value.mantissa().checked_mul(10i128.checked_pow(scale - value.scale())?);
+
+    let (mut x, mut y) = (digits(a)?, digits(b)?);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    Some(Decimal::from_i128_with_scale(x, scale).normalize())
+}
+
+/// A price as a whole number of the book's price unit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Price(i64);
+
+impl Price {
+    pub const fn new(units: i64) -> Self {
+        Self(units)
+    }
+
+    pub const fn units(self) -> i64 {
+        self.0
+    }
+
+    pub fn from_decimal(price: Decimal, unit: Decimal, rounding: Rounding) -> Result<Self, String> {
+        to_units(price, unit, rounding).map(Self)
+    }
+
+    pub fn to_decimal(self, unit: Decimal) -> Decimal {
+        to_decimal(self.0, unit)
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A quantity as a whole number of lots. Arithmetic panics on overflow
+/// rather than wrapping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Qty(i64);
+
+impl Qty {
+    pub const ZERO: Qty = Qty(0);
+
+    pub const fn new(lots: i64) -> Self {
+        Self(lots)
+    }
+
+    pub const fn units(self) -> i64 {
+        self.0
+    }
+
+    pub fn from_decimal(quantity: Decimal, lot_size: Decimal, rounding: Rounding) -> Result<Self, String> {
+        to_units(quantity, lot_size, rounding).map(Self)
+    }
+
+    pub fn to_decimal(self, lot_size: Decimal) -> Decimal {
+        to_decimal(self.0, lot_size)
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, other: Qty) -> Option<Qty> {
+        self.0.checked_add(other.0).map(Qty)
+    }
+
+    pub fn checked_sub(self, other: Qty) -> Option<Qty> {
+        self.0.checked_sub(other.0).map(Qty)
+    }
+
+    /// `self * numerator / denominator`, rounded down to a whole lot.
+    pub fn mul_div_floor(self, numerator: i128, denominator: i128) -> Qty {
+        let product = i128::from(self.0)
+            .checked_mul(numerator)
+            .unwrap_or_else(|| panic!("{} * {} overflows", self, numerator));
+        let lots = product
+            .checked_div_euclid(denominator)
+            .unwrap_or_else(|| panic!("{} / {} is undefined", product, denominator));
+        Qty(i64::try_from(lots).unwrap_or_else(|_| panic!("{} lots overflow a quantity", lots)))
+    }
+}
+
+impl fmt::Display for Qty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Add for Qty {
+    type Output = Qty;
+
+    fn add(self, other: Qty) -> Qty {
+        self.checked_add(other)
+            .unwrap_or_else(|| panic!("quantity overflow adding {} to {}", other, self))
+    }
+}
+
+impl Sub for Qty {
+    type Output = Qty;
+
+    fn sub(self, other: Qty) -> Qty {
+        self.checked_sub(other)
+            .unwrap_or_else(|| panic!("quantity overflow subtracting {} from {}", other, self))
+    }
+}
+
+impl AddAssign for Qty {
+    fn add_assign(&mut self, other: Qty) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Qty {
+    fn sub_assign(&mut self, other: Qty) {
+        *self = *self - other;
+    }
+}
+
+impl Sum for Qty {
+    fn sum<I: Iterator<Item = Qty>>(iter: I) -> Qty {
+        iter.fold(Qty::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Qty> for Qty {
+    fn sum<I: Iterator<Item = &'a Qty>>(iter: I) -> Qty {
+        iter.copied().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_conversion_rounding() {
+        let unit = dec!(0.005);
+
+        assert_eq!(Price::from_decimal(dec!(100.005), unit, Rounding::Exact), Ok(Price::new(20001)));
+        assert_eq!(Price::new(20001).to_decimal(unit), dec!(100.005));
+
+        let error = Price::from_decimal(dec!(100.007), unit, Rounding::Exact).unwrap_err();
+        assert!(error.contains("not a multiple of 0.005"), "{}", error);
+        assert_eq!(Price::from_decimal(dec!(100.007), unit, Rounding::Down), Ok(Price::new(20001)));
+        assert_eq!(Price::from_decimal(dec!(100.007), unit, Rounding::Up), Ok(Price::new(20002)));
+        assert_eq!(Price::from_decimal(dec!(-0.001), unit, Rounding::Down), Ok(Price::new(-1)));
+
+        assert_eq!(Qty::from_decimal(dec!(45), dec!(10), Rounding::Down), Ok(Qty::new(4)));
+        assert_eq!(Qty::from_decimal(dec!(45), dec!(10), Rounding::Up), Ok(Qty::new(5)));
+        assert_eq!(Qty::new(4).to_decimal(dec!(10)), dec!(40));
+        assert!(Qty::from_decimal(dec!(1), Decimal::ZERO, Rounding::Exact).is_err());
+    }
+
+    #[test]
+    fn test_overflow_is_checked() {
+        let error = Qty::from_decimal(dec!(1e20), dec!(1), Rounding::Exact).unwrap_err();
+        assert!(error.contains("overflows"), "{}", error);
+        assert!(Price::from_decimal(dec!(1e12), dec!(1e-10), Rounding::Down).is_err());
+
+        let max = Qty::new(i64::MAX);
+        assert_eq!(max.checked_add(Qty::new(1)), None);
+        assert_eq!(Qty::new(i64::MIN).checked_sub(Qty::new(1)), None);
+        assert!(std::panic::catch_unwind(|| max + Qty::new(1)).is_err());
+
+        // The intermediate product is widened, so only the result must fit
+        assert_eq!(max.mul_div_floor(3, 4), Qty::new(i64::MAX / 4 * 3 + 2));
+        assert!(std::panic::catch_unwind(|| max.mul_div_floor(2, 1)).is_err());
+    }
+
+    #[test]
+    fn test_common_unit() {
+        assert_eq!(common_unit(dec!(0.25), dec!(0.50)), Some(dec!(0.25)));
+        assert_eq!(common_unit(dec!(0.01), dec!(0.05)), Some(dec!(0.01)));
+        assert_eq!(common_unit(dec!(0.004), dec!(0.01)), Some(dec!(0.002)));
+        assert_eq!(common_unit(dec!(5), dec!(2.5)), Some(dec!(2.5)));
+        assert_eq!(common_unit(dec!(0), dec!(1)), None);
+
+        assert_eq!(price_grid([dec!(0.01), dec!(0.05)]), dec!(0.005));
+        assert_eq!(price_grid([dec!(0.25), dec!(0.50)]), dec!(0.125));
+        assert_eq!(price_grid([Decimal::ZERO]), DEFAULT_UNIT);
+    }
+}
@@ -0,0 +1,196 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::fixed::{price_grid, Price, Qty, Rounding, DEFAULT_UNIT};
+
+/// Reference data for a tradable symbol.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Instrument {
+    pub symbol: String,
+    /// Tick size by price: each `(from_price, tick_size)` applies from its
+    /// price up to the next entry. Kept sorted by price.
+    pub tick_table: Vec<(Decimal, Decimal)>,
+    pub lot_size: Decimal,
+    pub min_quantity: Decimal,
+    pub max_quantity: Option<Decimal>,
+    pub currency: String,
+    /// Units of the underlying per contract
+    pub multiplier: Decimal,
+}
+
+impl Instrument {
+    pub fn new(symbol: &str, tick_size: Decimal, currency: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            tick_table: vec![(Decimal::ZERO, tick_size)],
+            lot_size: Decimal::ONE,
+            min_quantity: Decimal::ONE,
+            max_quantity: None,
+            currency: currency.to_string(),
+            multiplier: Decimal::ONE,
+        }
+    }
+
+    pub fn with_tick_table(mut self, mut tick_table: Vec<(Decimal, Decimal)>) -> Self {
+        tick_table.sort_by_key(|(from_price, _)| *from_price);
+        self.tick_table = tick_table;
+        self
+    }
+
+    /// Sets the lot size, which also becomes the minimum quantity.
+    pub fn with_lot_size(mut self, lot_size: Decimal) -> Self {
+        self.lot_size = lot_size;
+        self.min_quantity = lot_size;
+        self
+    }
+
+    pub fn with_quantity_limits(mut self, min_quantity: Decimal, max_quantity: Option<Decimal>) -> Self {
+        self.min_quantity = min_quantity;
+        self.max_quantity = max_quantity;
+        self
+    }
+
+    pub fn with_multiplier(mut self, multiplier: Decimal) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn tick_size_at(&self, price: Decimal) -> Decimal {
+        self.tick_table
+            .iter()
+            .rev()
+            .find(|(from_price, _)| *from_price <= price)
+            .or_else(|| self.tick_table.first())
+            .map_or(Decimal::ZERO, |(_, tick_size)| *tick_size)
+    }
+
+    /// Smallest tick anywhere in the table.
+    pub fn min_tick_size(&self) -> Decimal {
+        self.tick_table
+            .iter()
+            .map(|(_, tick_size)| *tick_size)
+            .min()
+            .unwrap_or(Decimal::ZERO)
+    }
+
+    /// Value of one unit of a book's fixed-point prices.
+    pub fn price_unit(&self) -> Decimal {
+        price_grid(self.tick_table.iter().map(|(_, tick_size)| *tick_size))
+    }
+
+    /// Value of one unit of a book's fixed-point quantities.
+    pub fn quantity_unit(&self) -> Decimal {
+        if self.lot_size > Decimal::ZERO {
+            self.lot_size
+        } else {
+            DEFAULT_UNIT
+        }
+    }
+
+    pub fn notional(&self, price: Decimal, quantity: Decimal) -> Decimal {
+        price * quantity * self.multiplier
+    }
+
+    pub fn check_price(&self, price: Decimal) -> Result<(), String> {
+        let tick_size = self.tick_size_at(price);
+        if price <= Decimal::ZERO {
+            return Err(format!("Price {} must be positive for {}", price, self.symbol));
+        }
+        if tick_size > Decimal::ZERO && !(price % tick_size).is_zero() {
+            return Err(format!(
+                "Price {} is not a multiple of tick size {} for {}",
+                price, tick_size, self.symbol
+            ));
+        }
+        Price::from_decimal(price, self.price_unit(), Rounding::Exact)
+            .map_err(|e| format!("Price {} does not fit the price grid for {}: {}", price, self.symbol, e))?;
+        Ok(())
+    }
+
+    pub fn check_quantity(&self, quantity: Decimal) -> Result<(), String> {
+        if self.lot_size > Decimal::ZERO && !(quantity % self.lot_size).is_zero() {
+            return Err(format!(
+                "Quantity {} is not a multiple of lot size {} for {}",
+                quantity, self.lot_size, self.symbol
+            ));
+        }
+        Qty::from_decimal(quantity, self.quantity_unit(), Rounding::Exact)
+            .map_err(|e| format!("Quantity {} does not fit the lot grid for {}: {}", quantity, self.symbol, e))?;
+        if quantity < self.min_quantity {
+            return Err(format!(
+                "Quantity {} is below the minimum of {} for {}",
+                quantity, self.min_quantity, self.symbol
+            ));
+        }
+        if let Some(max_quantity) = self.max_quantity.filter(|max| quantity > *max) {
+            return Err(format!(
+                "Quantity {} is above the maximum of {} for {}",
+                quantity, max_quantity, self.symbol
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Order, OrderSide, OrderType};
+    use rust_decimal_macros::dec;
+
+    fn limit(quantity: Decimal, price: Decimal) -> Order {
+        Order::new(
+            "ES".to_string(),
+            OrderSide::Buy,
+            OrderType::Limit,
+            quantity,
+            Some(price),
+            None,
+            "test_user".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_tick_table() {
+        let instrument = Instrument::new("ES", dec!(0.01), "USD")
+            .with_tick_table(vec![
+                (dec!(10), dec!(0.05)),
+                (dec!(0), dec!(0.001)),
+                (dec!(100), dec!(0.25)),
+            ]);
+
+        assert_eq!(instrument.tick_size_at(dec!(5)), dec!(0.001));
+        assert_eq!(instrument.tick_size_at(dec!(10)), dec!(0.05));
+        assert_eq!(instrument.tick_size_at(dec!(250)), dec!(0.25));
+        assert_eq!(instrument.min_tick_size(), dec!(0.001));
+        assert_eq!(instrument.price_unit(), dec!(0.0005));
+
+        assert!(instrument.check_price(dec!(5.123)).is_ok());
+        assert!(instrument.check_price(dec!(50.10)).is_ok());
+        let error = instrument.check_price(dec!(100.10)).unwrap_err();
+        assert!(error.contains("tick size 0.25"), "{}", error);
+    }
+
+    #[test]
+    fn test_validate_order_for_instrument() {
+        let instrument = Instrument::new("ES", dec!(0.25), "USD")
+            .with_lot_size(dec!(10))
+            .with_quantity_limits(dec!(20), Some(dec!(1000)))
+            .with_multiplier(dec!(50));
+
+        let reason = |quantity, price| limit(quantity, price).validate_for(&instrument).unwrap_err();
+
+        assert!(limit(dec!(100), dec!(4500.25)).validate_for(&instrument).is_ok());
+        assert!(reason(dec!(105), dec!(4500.25)).contains("lot size"));
+        assert!(reason(dec!(10), dec!(4500.25)).contains("minimum"));
+        assert!(reason(dec!(2000), dec!(4500.25)).contains("maximum"));
+        assert!(reason(dec!(100), dec!(4500.10)).contains("tick size"));
+
+        // Without a tick size prices still have to fit the book's grid
+        let untick = Instrument::new("ES", Decimal::ZERO, "USD");
+        assert!(untick.check_price(dec!(4500.12345678)).is_ok());
+        assert!(untick.check_price(dec!(4500.123456789)).unwrap_err().contains("price grid"));
+
+        assert_eq!(instrument.notional(dec!(4500), dec!(2)), dec!(450000));
+    }
+}
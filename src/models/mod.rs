@@ -2,8 +2,25 @@ pub mod order;
 pub mod trade;
 pub mod orderbook;
 pub mod market_data;
+pub mod stop_book;
+pub mod auction;
+pub mod session;
+pub mod price_band;
+pub mod instrument;
+pub mod order_group;
+pub mod fixed;
 
-pub use order::{Order, OrderSide, OrderType, OrderStatus};
+pub use order::{
+    Order, OrderSide, OrderStatus, OrderType, Peg, PegType, PostOnly,
+    SelfTradePrevention, TimeInForce, TrailReference, TrailingOffset, TrailingStop,
+};
 pub use trade::Trade;
 pub use orderbook::OrderBook;
 pub use market_data::{MarketData, Ticker, Quote};
+pub use stop_book::StopBook;
+pub use auction::{AuctionKind, AuctionSummary, Uncross};
+pub use session::{SessionState, Timetable};
+pub use price_band::{BandAction, BandBreach, PriceBands};
+pub use instrument::Instrument;
+pub use order_group::{GroupActions, GroupKind, GroupStatus, OrderGroup};
+pub use fixed::{Price, Qty, Rounding};
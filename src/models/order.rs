@@ -3,12 +3,23 @@ use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use super::Instrument;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OrderSide {
     Buy,
     Sell,
 }
 
+impl OrderSide {
+    pub fn opposite(&self) -> Self {
+        match self {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OrderType {
     Market,
@@ -24,6 +35,133 @@ pub enum OrderStatus {
     Filled,
     Cancelled,
     Rejected,
+    Expired,
+    Replaced,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeInForce {
+    /// Good till cancelled
+    Gtc,
+    /// Immediate or cancel: the unfilled remainder never rests
+    Ioc,
+    /// Fill or kill: fills completely on arrival or not at all
+    Fok,
+    /// Expires at the end of the trading day it was entered on, taken as
+    /// its UTC date like session timetables
+    Day,
+    /// Good till date
+    Gtd(DateTime<Utc>),
+}
+
+/// What to do with a post-only order that would cross the opposite touch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PostOnly {
+    Reject,
+    /// Reprice one tick behind the opposite best price
+    Slide,
+}
+
+/// How to stop two orders from the same user trading with each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SelfTradePrevention {
+    CancelResting,
+    CancelAggressing,
+    CancelBoth,
+    /// Reduce both orders by the smaller remaining quantity, cancelling
+    /// whichever is used up
+    DecrementAndCancel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrailingOffset {
+    Amount(Decimal),
+    /// Percentage of the reference price, e.g. `2` for 2%
+    Percent(Decimal),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrailReference {
+    LastTrade,
+    /// Best bid for sell stops, best ask for buy stops
+    BestPrice,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrailingStop {
+    pub offset: TrailingOffset,
+    pub reference: TrailReference,
+}
+
+impl TrailingStop {
+    /// The stop price implied by `reference` for an order on `side`, rounded
+    /// away from the reference onto the tick grid.
+    pub fn stop_price(&self, side: OrderSide, reference: Decimal, tick_size: Decimal) -> Decimal {
+        let distance = match self.offset {
+            TrailingOffset::Amount(amount) => amount,
+            TrailingOffset::Percent(percent) => reference * percent / Decimal::ONE_HUNDRED,
+        };
+
+        let stop_price = match side {
+            OrderSide::Buy => reference + distance,
+            OrderSide::Sell => reference - distance,
+        };
+
+        if tick_size <= Decimal::ZERO {
+            return stop_price;
+        }
+
+        let ticks = stop_price / tick_size;
+        let ticks = match side {
+            OrderSide::Buy => ticks.ceil(),
+            OrderSide::Sell => ticks.floor(),
+        };
+        ticks * tick_size
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PegType {
+    /// Same-side best price: best bid for buys, best ask for sells
+    Primary,
+    /// Opposite-side best price: best ask for buys, best bid for sells
+    Market,
+    Midpoint,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Peg {
+    pub peg_type: PegType,
+    /// Signed amount added to the reference price
+    pub offset: Option<Decimal>,
+    /// Highest price for a pegged buy, lowest price for a pegged sell
+    pub cap: Option<Decimal>,
+}
+
+impl Peg {
+    /// The price a pegged order on `side` should rest at for the given
+    /// reference BBO, or `None` if the reference is missing.
+    pub fn price(
+        &self,
+        side: OrderSide,
+        best_bid: Option<Decimal>,
+        best_ask: Option<Decimal>,
+    ) -> Option<Decimal> {
+        let reference = match (self.peg_type, side) {
+            (PegType::Primary, OrderSide::Buy) | (PegType::Market, OrderSide::Sell) => best_bid?,
+            (PegType::Primary, OrderSide::Sell) | (PegType::Market, OrderSide::Buy) => best_ask?,
+            (PegType::Midpoint, _) => (best_bid? + best_ask?) / Decimal::TWO,
+        };
+
+        let price = reference + self.offset.unwrap_or(Decimal::ZERO);
+        let price = match (side, self.cap) {
+            (OrderSide::Buy, Some(cap)) => price.min(cap),
+            (OrderSide::Sell, Some(cap)) => price.max(cap),
+            (_, None) => price,
+        };
+
+        (price > Decimal::ZERO).then_some(price)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,6 +175,24 @@ pub struct Order {
     pub price: Option<Decimal>,
     pub stop_price: Option<Decimal>,
     pub status: OrderStatus,
+    pub time_in_force: TimeInForce,
+    pub post_only: Option<PostOnly>,
+    pub display_quantity: Option<Decimal>,
+    /// Rests without showing in depth or market data, behind displayed orders
+    pub hidden: bool,
+    pub trailing_stop: Option<TrailingStop>,
+    pub peg: Option<Peg>,
+    pub self_trade_prevention: Option<SelfTradePrevention>,
+    /// Worst price a market order may trade at before its remainder is cancelled
+    pub protection_price: Option<Decimal>,
+    /// Smallest quantity this order accepts in a single execution
+    pub min_quantity: Option<Decimal>,
+    /// Only trade when the whole remaining quantity can trade at once
+    pub all_or_none: bool,
+    /// The order this one replaced through cancel-replace
+    pub replaces: Option<Uuid>,
+    /// The order that replaced this one through cancel-replace
+    pub replaced_by: Option<Uuid>,
     pub user_id: String,
     pub timestamp: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -63,12 +219,133 @@ impl Order {
             price,
             stop_price,
             status: OrderStatus::Pending,
+            time_in_force: TimeInForce::Gtc,
+            post_only: None,
+            display_quantity: None,
+            hidden: false,
+            trailing_stop: None,
+            peg: None,
+            self_trade_prevention: None,
+            protection_price: None,
+            min_quantity: None,
+            all_or_none: false,
+            replaces: None,
+            replaced_by: None,
             user_id,
             timestamp: now,
             updated_at: now,
         }
     }
 
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = time_in_force;
+        self
+    }
+
+    pub fn with_post_only(mut self, post_only: PostOnly) -> Self {
+        self.post_only = Some(post_only);
+        self
+    }
+
+    /// Turns the order into an iceberg that only shows `display_quantity`
+    /// at a time.
+    pub fn with_display_quantity(mut self, display_quantity: Decimal) -> Self {
+        self.display_quantity = Some(display_quantity);
+        self
+    }
+
+    pub fn with_hidden(mut self) -> Self {
+        self.hidden = true;
+        self
+    }
+
+    /// Makes a stop order trail `reference` by `offset`. Without an explicit
+    /// stop price the initial stop is taken from the reference on entry.
+    pub fn with_trailing_stop(mut self, offset: TrailingOffset, reference: TrailReference) -> Self {
+        self.trailing_stop = Some(TrailingStop { offset, reference });
+        self
+    }
+
+    /// Pegs a limit order to the BBO. The engine sets and maintains the
+    /// price, so the order can be created without one.
+    pub fn with_peg(mut self, peg_type: PegType, offset: Option<Decimal>, cap: Option<Decimal>) -> Self {
+        self.peg = Some(Peg { peg_type, offset, cap });
+        self
+    }
+
+    pub fn with_self_trade_prevention(mut self, mode: SelfTradePrevention) -> Self {
+        self.self_trade_prevention = Some(mode);
+        self
+    }
+
+    /// Caps how far a market order can walk the book.
+    pub fn with_protection_price(mut self, protection_price: Decimal) -> Self {
+        self.protection_price = Some(protection_price);
+        self
+    }
+
+    /// Skips executions smaller than `min_quantity`, or than whatever is
+    /// left of the order once that is less.
+    pub fn with_min_quantity(mut self, min_quantity: Decimal) -> Self {
+        self.min_quantity = Some(min_quantity);
+        self
+    }
+
+    pub fn with_all_or_none(mut self) -> Self {
+        self.all_or_none = true;
+        self
+    }
+
+    /// Smallest execution this order accepts right now.
+    pub fn min_execution(&self) -> Decimal {
+        let remaining = self.remaining_quantity();
+        if self.all_or_none {
+            remaining
+        } else {
+            self.min_quantity.map_or(Decimal::ZERO, |min| min.min(remaining))
+        }
+    }
+
+    /// Moves the stop price only if `stop_price` is more favorable than the
+    /// current one. Stop-limit orders keep their distance between stop and
+    /// limit price. Returns whether the stop moved.
+    pub fn trail_stop_to(&mut self, stop_price: Decimal) -> bool {
+        let improves = match (self.side, self.stop_price) {
+            (_, None) => true,
+            (OrderSide::Buy, Some(current)) => stop_price < current,
+            (OrderSide::Sell, Some(current)) => stop_price > current,
+        };
+
+        if !improves {
+            return false;
+        }
+
+        if let (OrderType::StopLimit, Some(price), Some(current)) =
+            (self.order_type, self.price, self.stop_price)
+        {
+            self.price = Some(price + stop_price - current);
+        }
+        self.stop_price = Some(stop_price);
+        self.updated_at = Utc::now();
+        true
+    }
+
+    pub fn is_stop(&self) -> bool {
+        matches!(self.order_type, OrderType::StopLoss | OrderType::StopLimit)
+    }
+
+    pub fn is_active(&self) -> bool {
+        matches!(self.status, OrderStatus::Pending | OrderStatus::PartiallyFilled)
+    }
+
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        match self.time_in_force {
+            TimeInForce::Day => self.timestamp.date_naive() < now.date_naive(),
+            TimeInForce::Gtd(expire_at) => expire_at <= now,
+            TimeInForce::Gtc | TimeInForce::Ioc | TimeInForce::Fok => false,
+        }
+    }
+
     pub fn is_fully_filled(&self) -> bool {
         self.filled_quantity >= self.quantity
     }
@@ -77,6 +354,15 @@ impl Order {
         self.quantity - self.filled_quantity
     }
 
+    /// The part of the remaining quantity shown in the book.
+    pub fn displayed_quantity(&self) -> Decimal {
+        match self.display_quantity {
+            _ if self.hidden => Decimal::ZERO,
+            Some(display) => display.min(self.remaining_quantity()),
+            None => self.remaining_quantity(),
+        }
+    }
+
     pub fn fill(&mut self, quantity: Decimal) {
         self.filled_quantity += quantity;
         self.updated_at = Utc::now();
@@ -98,27 +384,142 @@ impl Order {
         self.updated_at = Utc::now();
     }
 
+    /// Removes `quantity` from the open quantity without a fill, cancelling
+    /// the order once nothing is left.
+    pub fn decrement(&mut self, quantity: Decimal) {
+        self.quantity -= quantity.min(self.remaining_quantity());
+        self.updated_at = Utc::now();
+
+        if self.remaining_quantity() <= Decimal::ZERO {
+            self.status = OrderStatus::Cancelled;
+        }
+    }
+
+    pub fn replace(&mut self, replaced_by: Uuid) {
+        self.status = OrderStatus::Replaced;
+        self.replaced_by = Some(replaced_by);
+        self.updated_at = Utc::now();
+    }
+
+    pub fn expire(&mut self) {
+        self.status = OrderStatus::Expired;
+        self.updated_at = Utc::now();
+    }
+
     pub fn validate(&self) -> Result<(), String> {
         if self.quantity <= Decimal::ZERO {
             return Err("Quantity must be positive".to_string());
         }
 
-        match self.order_type {
-            OrderType::Limit | OrderType::StopLimit => {
-                if self.price.is_none() || self.price.unwrap() <= Decimal::ZERO {
-                    return Err("Limit orders must have a positive price".to_string());
-                }
+        if matches!(self.order_type, OrderType::Limit | OrderType::StopLimit)
+            && self.peg.is_none()
+            && !matches!(self.price, Some(price) if price > Decimal::ZERO)
+        {
+            return Err("Limit orders must have a positive price".to_string());
+        }
+
+        if self.is_stop()
+            && self.trailing_stop.is_none()
+            && !matches!(self.stop_price, Some(stop) if stop > Decimal::ZERO)
+        {
+            return Err("Stop orders must have a positive stop price".to_string());
+        }
+
+        if let Some(protection_price) = self.protection_price {
+            if !matches!(self.order_type, OrderType::Market | OrderType::StopLoss) {
+                return Err("Protection prices only apply to market orders".to_string());
             }
-            _ => {}
+            if protection_price <= Decimal::ZERO {
+                return Err("Protection price must be positive".to_string());
+            }
+        }
+
+        if self.peg.is_some() && self.order_type != OrderType::Limit {
+            return Err("Pegged orders must be limit orders".to_string());
         }
 
-        match self.order_type {
-            OrderType::StopLoss | OrderType::StopLimit => {
-                if self.stop_price.is_none() || self.stop_price.unwrap() <= Decimal::ZERO {
-                    return Err("Stop orders must have a positive stop price".to_string());
+        if let Some(trailing) = self.trailing_stop {
+            if !self.is_stop() {
+                return Err("Trailing offsets are only supported on stop orders".to_string());
+            }
+            let valid = match trailing.offset {
+                TrailingOffset::Amount(amount) => amount > Decimal::ZERO,
+                TrailingOffset::Percent(percent) => {
+                    percent > Decimal::ZERO && percent < Decimal::ONE_HUNDRED
                 }
+            };
+            if !valid {
+                return Err("Trailing offset must be positive and below 100%".to_string());
+            }
+        }
+
+        if self.post_only.is_some() {
+            if self.order_type != OrderType::Limit {
+                return Err("Post-only is only supported on limit orders".to_string());
+            }
+            if matches!(self.time_in_force, TimeInForce::Ioc | TimeInForce::Fok) {
+                return Err("Post-only orders cannot be immediate-or-cancel or fill-or-kill".to_string());
+            }
+        }
+
+        if let Some(display) = self.display_quantity {
+            if !matches!(self.order_type, OrderType::Limit | OrderType::StopLimit) {
+                return Err("Iceberg orders must be limit orders".to_string());
+            }
+            if display <= Decimal::ZERO || display > self.quantity {
+                return Err("Display quantity must be positive and no larger than the order quantity".to_string());
+            }
+        }
+
+        if self.hidden {
+            if !matches!(self.order_type, OrderType::Limit | OrderType::StopLimit) {
+                return Err("Hidden orders must be limit orders".to_string());
+            }
+            if self.display_quantity.is_some() {
+                return Err("Hidden orders cannot also be icebergs".to_string());
+            }
+        }
+
+        if let Some(min_quantity) = self.min_quantity {
+            if min_quantity <= Decimal::ZERO || min_quantity > self.quantity {
+                return Err("Minimum quantity must be positive and no larger than the order quantity".to_string());
+            }
+        }
+
+        if self.all_or_none && self.display_quantity.is_some() {
+            return Err("All-or-none orders cannot be icebergs".to_string());
+        }
+
+        if let TimeInForce::Gtd(expire_at) = self.time_in_force {
+            if expire_at <= self.timestamp {
+                return Err("Good-till-date expiry must be in the future".to_string());
             }
-            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Validates the order on its own and against the instrument it trades.
+    /// Prices of pegged orders are set by the engine and are not checked.
+    pub fn validate_for(&self, instrument: &Instrument) -> Result<(), String> {
+        self.validate()?;
+
+        if self.symbol != instrument.symbol {
+            return Err(format!("Order for {} checked against {}", self.symbol, instrument.symbol));
+        }
+
+        instrument.check_quantity(self.quantity)?;
+        if let Some(display_quantity) = self.display_quantity {
+            instrument.check_quantity(display_quantity)?;
+        }
+
+        let prices = [
+            self.price.filter(|_| self.peg.is_none()),
+            self.stop_price,
+            self.protection_price,
+        ];
+        for price in prices.into_iter().flatten() {
+            instrument.check_price(price)?;
         }
 
         Ok(())
@@ -194,5 +595,91 @@ mod tests {
             "user123".to_string(),
         );
         assert!(invalid_order.validate().is_err());
+
+        let expired_gtd = valid_order
+            .clone()
+            .with_time_in_force(TimeInForce::Gtd(valid_order.timestamp));
+        assert!(expired_gtd.validate().is_err());
+
+        let post_only_ioc = valid_order
+            .with_post_only(PostOnly::Reject)
+            .with_time_in_force(TimeInForce::Ioc);
+        assert!(post_only_ioc.validate().is_err());
+    }
+
+    #[test]
+    fn test_trailing_stop_price() {
+        let amount = TrailingStop {
+            offset: TrailingOffset::Amount(dec!(1.50)),
+            reference: TrailReference::LastTrade,
+        };
+        assert_eq!(amount.stop_price(OrderSide::Sell, dec!(100.00), dec!(0.01)), dec!(98.50));
+        assert_eq!(amount.stop_price(OrderSide::Buy, dec!(100.00), dec!(0.01)), dec!(101.50));
+
+        let percent = TrailingStop {
+            offset: TrailingOffset::Percent(dec!(2.5)),
+            reference: TrailReference::LastTrade,
+        };
+        assert_eq!(percent.stop_price(OrderSide::Sell, dec!(101.00), dec!(0.05)), dec!(98.45));
+        assert_eq!(percent.stop_price(OrderSide::Buy, dec!(101.00), dec!(0.05)), dec!(103.55));
+
+        let mut order = Order::new(
+            "AAPL".to_string(),
+            OrderSide::Sell,
+            OrderType::StopLimit,
+            dec!(100),
+            Some(dec!(97.50)),
+            Some(dec!(98.00)),
+            "user123".to_string(),
+        );
+        assert!(order.trail_stop_to(dec!(99.00)));
+        assert_eq!(order.price, Some(dec!(98.50)));
+        assert!(!order.trail_stop_to(dec!(98.50)));
+        assert_eq!(order.stop_price, Some(dec!(99.00)));
+    }
+
+    #[test]
+    fn test_peg_price() {
+        let (bid, ask) = (Some(dec!(100.00)), Some(dec!(100.01)));
+
+        let primary = Peg { peg_type: PegType::Primary, offset: Some(dec!(-0.02)), cap: None };
+        assert_eq!(primary.price(OrderSide::Buy, bid, ask), Some(dec!(99.98)));
+        assert_eq!(primary.price(OrderSide::Sell, bid, ask), Some(dec!(99.99)));
+
+        let market = Peg { peg_type: PegType::Market, offset: None, cap: Some(dec!(99.50)) };
+        assert_eq!(market.price(OrderSide::Buy, bid, ask), Some(dec!(99.50)));
+        assert_eq!(market.price(OrderSide::Sell, bid, ask), Some(dec!(100.00)));
+
+        let midpoint = Peg { peg_type: PegType::Midpoint, offset: None, cap: None };
+        assert_eq!(midpoint.price(OrderSide::Buy, bid, ask), Some(dec!(100.005)));
+        assert_eq!(midpoint.price(OrderSide::Buy, bid, None), None);
+    }
+
+    #[test]
+    fn test_time_in_force_expiry() {
+        let mut order = Order::new(
+            "AAPL".to_string(),
+            OrderSide::Buy,
+            OrderType::Limit,
+            dec!(100),
+            Some(dec!(150.50)),
+            None,
+            "user123".to_string(),
+        );
+        order.timestamp = "2024-03-01T10:00:00Z".parse().unwrap();
+        let later = order.timestamp + chrono::Duration::hours(1);
+        let next_day = "2024-03-02T00:00:00Z".parse().unwrap();
+
+        assert!(!order.is_expired_at(next_day));
+
+        // DAY orders live until the trading date ends, not just until entry
+        let day = order.clone().with_time_in_force(TimeInForce::Day);
+        assert!(!day.is_expired_at(later));
+        assert!(!day.is_expired_at("2024-03-01T23:59:59Z".parse().unwrap()));
+        assert!(day.is_expired_at(next_day));
+
+        let gtd = order.with_time_in_force(TimeInForce::Gtd(later));
+        assert!(!gtd.is_expired_at(gtd.timestamp + chrono::Duration::minutes(30)));
+        assert!(gtd.is_expired_at(later));
     }
 }
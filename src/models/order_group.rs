@@ -0,0 +1,240 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::Order;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GroupKind {
+    /// One-cancels-other: the first fill or cancel on a leg cancels the rest
+    Oco,
+    /// One-triggers-other: children are held until the parent is done trading
+    Oto,
+    /// An entry whose take-profit and stop-loss children form an OCO pair
+    /// once released. A partial fill on one exit reduces the other by as much
+    Bracket,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GroupStatus {
+    /// All legs working, nothing traded yet
+    Working,
+    /// The group has reacted to a fill and orders are still working
+    Triggered,
+    /// Nothing is working any more and something traded
+    Completed,
+    /// Nothing is working any more and nothing traded
+    Cancelled,
+}
+
+/// Orders linked by a contingency.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderGroup {
+    pub id: Uuid,
+    pub kind: GroupKind,
+    /// Orders currently live under the group's rule: the OCO legs, the
+    /// parent, or the released children
+    pub legs: Vec<Uuid>,
+    /// Children held back until the parent is done trading
+    pub pending: Vec<Order>,
+    /// Entry quantity a released bracket's exits close out between them
+    pub protected: Decimal,
+    pub status: GroupStatus,
+}
+
+/// What the engine has to do to bring a group in line with its legs.
+#[derive(Debug, Clone, Default)]
+pub struct GroupActions {
+    pub cancel: Vec<Uuid>,
+    /// Working orders to amend down to the given total quantity
+    pub reduce: Vec<(Uuid, Decimal)>,
+    pub release: Vec<Order>,
+}
+
+impl OrderGroup {
+    pub fn new(kind: GroupKind, legs: Vec<Uuid>, pending: Vec<Order>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            kind,
+            legs,
+            pending,
+            protected: Decimal::ZERO,
+            status: GroupStatus::Working,
+        }
+    }
+
+    /// Applies the group's rule to the current state of its legs. Legs not
+    /// yet known to the engine are passed as missing and ignored.
+    pub fn update(&mut self, legs: &[Order]) -> GroupActions {
+        let mut actions = GroupActions::default();
+
+        if !self.pending.is_empty() {
+            // Only the parent is live; release children once it is done trading
+            let Some(parent) = legs.first() else {
+                return actions;
+            };
+
+            if parent.is_fully_filled() || (!parent.is_active() && parent.filled_quantity > Decimal::ZERO) {
+                let mut children = std::mem::take(&mut self.pending);
+                if self.kind == GroupKind::Bracket {
+                    // Protect exactly the position the entry opened
+                    for child in &mut children {
+                        child.quantity = parent.filled_quantity;
+                    }
+                    self.protected = parent.filled_quantity;
+                }
+
+                self.legs = children.iter().map(|child| child.id).collect();
+                self.status = GroupStatus::Triggered;
+                actions.release = children;
+            } else if !parent.is_active() {
+                self.pending.clear();
+                self.status = GroupStatus::Cancelled;
+            }
+
+            return actions;
+        }
+
+        let open = self.protected - legs.iter().map(|leg| leg.filled_quantity).sum::<Decimal>();
+        let resolved = match self.kind {
+            GroupKind::Oco => legs
+                .iter()
+                .any(|leg| leg.filled_quantity > Decimal::ZERO || !leg.is_active()),
+            // An exit only takes the other one down once it is done; until
+            // then whatever it fills comes off the other exit
+            GroupKind::Bracket => open <= Decimal::ZERO || legs.iter().any(|leg| !leg.is_active()),
+            GroupKind::Oto => false,
+        };
+
+        if resolved {
+            actions.cancel = legs
+                .iter()
+                .filter(|leg| leg.is_active() && (self.kind == GroupKind::Bracket || leg.filled_quantity.is_zero()))
+                .map(|leg| leg.id)
+                .collect();
+        } else if self.kind == GroupKind::Bracket {
+            actions.reduce = legs
+                .iter()
+                .filter(|leg| leg.is_active() && leg.remaining_quantity() > open)
+                .map(|leg| (leg.id, leg.filled_quantity + open))
+                .collect();
+        }
+
+        let traded = legs.iter().any(|leg| leg.filled_quantity > Decimal::ZERO);
+        let working = legs
+            .iter()
+            .any(|leg| leg.is_active() && !actions.cancel.contains(&leg.id));
+
+        self.status = if working {
+            if traded || resolved || self.status == GroupStatus::Triggered {
+                GroupStatus::Triggered
+            } else {
+                GroupStatus::Working
+            }
+        } else if traded || self.kind != GroupKind::Oco {
+            // Released children only exist because their parent traded
+            GroupStatus::Completed
+        } else {
+            GroupStatus::Cancelled
+        };
+
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{OrderSide, OrderType};
+    use rust_decimal_macros::dec;
+
+    fn leg(side: OrderSide, price: Decimal) -> Order {
+        Order::new(
+            "AAPL".to_string(),
+            side,
+            OrderType::Limit,
+            dec!(100),
+            Some(price),
+            None,
+            "test_user".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_oco_fill_cancels_sibling() {
+        let mut first = leg(OrderSide::Sell, dec!(110));
+        let second = leg(OrderSide::Sell, dec!(120));
+        let mut group = OrderGroup::new(GroupKind::Oco, vec![first.id, second.id], Vec::new());
+
+        assert!(group.update(&[first.clone(), second.clone()]).cancel.is_empty());
+        assert_eq!(group.status, GroupStatus::Working);
+
+        // A partial fill is enough to resolve the group
+        first.fill(dec!(30));
+        let actions = group.update(&[first.clone(), second.clone()]);
+        assert_eq!(actions.cancel, vec![second.id]);
+        assert_eq!(group.status, GroupStatus::Triggered);
+
+        first.fill(dec!(70));
+        let mut second = second;
+        second.cancel();
+        assert!(group.update(&[first, second]).cancel.is_empty());
+        assert_eq!(group.status, GroupStatus::Completed);
+    }
+
+    #[test]
+    fn test_bracket_partial_exit_reduces_sibling() {
+        let mut entry = leg(OrderSide::Buy, dec!(100));
+        let mut take_profit = leg(OrderSide::Sell, dec!(110));
+        let mut stop_loss = leg(OrderSide::Sell, dec!(90));
+        let exits = vec![take_profit.clone(), stop_loss.clone()];
+        let mut group = OrderGroup::new(GroupKind::Bracket, vec![entry.id], exits);
+
+        entry.fill(dec!(100));
+        assert_eq!(group.update(&[entry]).release.len(), 2);
+
+        // 30 of the position is closed, so only 70 is left to protect
+        take_profit.fill(dec!(30));
+        let actions = group.update(&[take_profit.clone(), stop_loss.clone()]);
+        assert!(actions.cancel.is_empty());
+        assert_eq!(actions.reduce, vec![(stop_loss.id, dec!(70))]);
+        assert_eq!(group.status, GroupStatus::Triggered);
+
+        // Once the take-profit completes the stop-loss goes
+        stop_loss.quantity = dec!(70);
+        take_profit.fill(dec!(70));
+        let actions = group.update(&[take_profit.clone(), stop_loss.clone()]);
+        assert_eq!(actions.cancel, vec![stop_loss.id]);
+        assert!(actions.reduce.is_empty());
+
+        stop_loss.cancel();
+        group.update(&[take_profit, stop_loss]);
+        assert_eq!(group.status, GroupStatus::Completed);
+    }
+
+    #[test]
+    fn test_bracket_releases_children_for_filled_quantity() {
+        let mut entry = leg(OrderSide::Buy, dec!(100));
+        let take_profit = leg(OrderSide::Sell, dec!(110));
+        let mut group = OrderGroup::new(GroupKind::Bracket, vec![entry.id], vec![take_profit.clone()]);
+
+        entry.fill(dec!(40));
+        assert!(group.update(std::slice::from_ref(&entry)).release.is_empty());
+
+        // Cancelling the rest of the entry releases protection for 40
+        entry.cancel();
+        let actions = group.update(&[entry]);
+        assert_eq!(actions.release.len(), 1);
+        assert_eq!(actions.release[0].quantity, dec!(40));
+        assert_eq!(group.legs, vec![take_profit.id]);
+        assert_eq!(group.status, GroupStatus::Triggered);
+
+        // An OTO parent cancelled before trading takes its children with it
+        let mut parent = leg(OrderSide::Buy, dec!(100));
+        let mut group = OrderGroup::new(GroupKind::Oto, vec![parent.id], vec![take_profit]);
+        parent.cancel();
+        assert!(group.update(&[parent]).release.is_empty());
+        assert_eq!(group.status, GroupStatus::Cancelled);
+        assert!(group.pending.is_empty());
+    }
+}
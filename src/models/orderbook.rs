@@ -1,90 +1,483 @@
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use uuid::Uuid;
 
-use super::{Order, OrderSide};
+use super::fixed::{price_grid, Price, Qty, Rounding};
+use super::{AuctionKind, Order, OrderSide};
+
+/// Position of an order's node in the book's arena. Stays valid until the
+/// order leaves the book.
+pub type OrderHandle = usize;
+
+/// An order resting at a price level with the quantity it can currently
+/// trade: the displayed slice of a lit order, or all of a hidden one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LevelEntry {
+    pub order_id: Uuid,
+    pub quantity: Qty,
+    /// Everything left of the order, including an iceberg's reserve
+    pub remaining: Qty,
+    /// Size an iceberg's displayed slice refreshes to
+    pub display: Option<Qty>,
+    pub hidden: bool,
+    /// Set for pegged orders, which never count toward the peg reference
+    pub pegged: bool,
+}
+
+impl LevelEntry {
+    /// The next slice an order with `remaining` left can show.
+    pub fn slice(&self, remaining: Qty) -> Qty {
+        self.display.map_or(remaining, |display| display.min(remaining))
+    }
+}
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Node {
+    entry: LevelEntry,
+    side: OrderSide,
+    price: Price,
+    prev: Option<OrderHandle>,
+    next: Option<OrderHandle>,
+}
+
+/// Slab of order nodes. Freed slots are reused, so a busy book stops
+/// allocating once it has reached its working size.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Arena {
+    nodes: Vec<Option<Node>>,
+    free: Vec<OrderHandle>,
+}
+
+impl Arena {
+    fn insert(&mut self, node: Node) -> OrderHandle {
+        match self.free.pop() {
+            Some(handle) => {
+                self.nodes[handle] = Some(node);
+                handle
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn remove(&mut self, handle: OrderHandle) -> Option<Node> {
+        let node = self.nodes.get_mut(handle)?.take()?;
+        self.free.push(handle);
+        Some(node)
+    }
+
+    fn get(&self, handle: OrderHandle) -> Option<&Node> {
+        self.nodes.get(handle)?.as_ref()
+    }
+
+    fn get_mut(&mut self, handle: OrderHandle) -> Option<&mut Node> {
+        self.nodes.get_mut(handle)?.as_mut()
+    }
+}
+
+/// Head and tail of a doubly linked queue of arena nodes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Queue {
+    head: Option<OrderHandle>,
+    tail: Option<OrderHandle>,
+}
+
+/// Orders resting at one price. Displayed entries come first in time
+/// priority, hidden entries queue behind all of them. The entries live in
+/// the book's arena; walk them with `OrderBook::queue`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PriceLevel {
-    pub price: Decimal,
-    pub total_quantity: Decimal,
-    pub orders: Vec<Uuid>,
+    pub price: Price,
+    /// Displayed quantity, the only part that shows in public depth
+    pub lit_quantity: Qty,
+    pub dark_quantity: Qty,
+    pub order_count: usize,
+    lit: Queue,
+    dark: Queue,
 }
 
 impl PriceLevel {
-    pub fn new(price: Decimal) -> Self {
+    pub fn new(price: Price) -> Self {
         Self {
             price,
-            total_quantity: Decimal::ZERO,
-            orders: Vec::new(),
+            lit_quantity: Qty::ZERO,
+            dark_quantity: Qty::ZERO,
+            order_count: 0,
+            lit: Queue::default(),
+            dark: Queue::default(),
         }
     }
 
-    pub fn add_order(&mut self, order_id: Uuid, quantity: Decimal) {
-        self.orders.push(order_id);
-        self.total_quantity += quantity;
+    pub fn total_quantity(&self) -> Qty {
+        self.lit_quantity + self.dark_quantity
     }
 
-    pub fn remove_order(&mut self, order_id: Uuid, quantity: Decimal) {
-        self.orders.retain(|&id| id != order_id);
-        self.total_quantity -= quantity;
+    pub fn is_empty(&self) -> bool {
+        self.order_count == 0
+    }
+
+    fn quantity_mut(&mut self, hidden: bool) -> &mut Qty {
+        if hidden {
+            &mut self.dark_quantity
+        } else {
+            &mut self.lit_quantity
+        }
+    }
+
+    fn queue_mut(&mut self, hidden: bool) -> &mut Queue {
+        if hidden {
+            &mut self.dark
+        } else {
+            &mut self.lit
+        }
     }
 }
 
+/// Walks a level's queue in priority order: displayed entries, then hidden.
+struct LevelIter<'a> {
+    arena: &'a Arena,
+    next: Option<OrderHandle>,
+    dark: Option<OrderHandle>,
+}
+
+impl<'a> Iterator for LevelIter<'a> {
+    type Item = &'a LevelEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let handle = self.next.or_else(|| self.dark.take())?;
+        let node = self.arena.get(handle)?;
+        self.next = node.next;
+        Some(&node.entry)
+    }
+}
+
+/// Resting orders by price. Prices and quantities are kept as fixed-point
+/// `Price` and `Qty` on the book's grid; methods taking or returning
+/// `Decimal` convert at the boundary.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderBook {
     pub symbol: String,
-    pub bids: BTreeMap<Decimal, PriceLevel>,
-    pub asks: BTreeMap<Decimal, PriceLevel>,
+    pub bids: BTreeMap<Price, PriceLevel>,
+    pub asks: BTreeMap<Price, PriceLevel>,
+    pub last_trade_price: Option<Decimal>,
+    pub tick_size: Decimal,
+    /// Value of one `Price` unit
+    price_unit: Decimal,
+    /// Smallest tradable quantity increment and the value of one `Qty`
+    lot_size: Decimal,
+    /// Resting pegged orders in arrival order
+    pub pegged: Vec<Uuid>,
+    /// Set while the book is in a call auction and orders rest without matching
+    pub auction: Option<AuctionKind>,
+    arena: Arena,
+    index: HashMap<Uuid, OrderHandle>,
 }
 
 impl OrderBook {
     pub fn new(symbol: String) -> Self {
+        Self::with_tick_size(symbol, Decimal::new(1, 2))
+    }
+
+    pub fn with_tick_size(symbol: String, tick_size: Decimal) -> Self {
         Self {
             symbol,
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
+            last_trade_price: None,
+            tick_size,
+            price_unit: price_grid([tick_size]),
+            lot_size: Decimal::ONE,
+            pegged: Vec::new(),
+            auction: None,
+            arena: Arena::default(),
+            index: HashMap::new(),
         }
     }
 
-    pub fn add_order(&mut self, order: &Order) {
-        let price = order.price.unwrap_or(Decimal::ZERO);
-        let quantity = order.remaining_quantity();
+    pub fn price_unit(&self) -> Decimal {
+        self.price_unit
+    }
+
+    pub fn lot_size(&self) -> Decimal {
+        self.lot_size
+    }
 
-        let book = match order.side {
-            OrderSide::Buy => &mut self.bids,
-            OrderSide::Sell => &mut self.asks,
+    pub fn to_price(&self, price: Decimal, rounding: Rounding) -> Result<Price, String> {
+        Price::from_decimal(price, self.price_unit, rounding)
+    }
+
+    pub fn price_value(&self, price: Price) -> Decimal {
+        price.to_decimal(self.price_unit)
+    }
+
+    pub fn to_qty(&self, quantity: Decimal, rounding: Rounding) -> Result<Qty, String> {
+        Qty::from_decimal(quantity, self.lot_size, rounding)
+    }
+
+    pub fn qty_value(&self, quantity: Qty) -> Decimal {
+        quantity.to_decimal(self.lot_size)
+    }
+
+    /// Brings `price` onto the grid without making it more aggressive for
+    /// an order on `side`: buys round down and sells round up.
+    pub fn passive_price(&self, side: OrderSide, price: Decimal) -> Result<Price, String> {
+        let rounding = match side {
+            OrderSide::Buy => Rounding::Down,
+            OrderSide::Sell => Rounding::Up,
         };
+        self.to_price(price, rounding)
+    }
 
-        book.entry(price)
-            .or_insert_with(|| PriceLevel::new(price))
-            .add_order(order.id, quantity);
+    /// Moves the book onto a new price and lot grid. Resting orders must be
+    /// whole multiples of the new units; if any is not, nothing changes.
+    pub fn rescale(&mut self, price_unit: Decimal, lot_size: Decimal) -> Result<(), String> {
+        if price_unit <= Decimal::ZERO || lot_size <= Decimal::ZERO {
+            return Err(format!("Grid of {} by {} must be positive", price_unit, lot_size));
+        }
+        if (price_unit, lot_size) == (self.price_unit, self.lot_size) {
+            return Ok(());
+        }
+
+        let price = |old: Price| Price::from_decimal(self.price_value(old), price_unit, Rounding::Exact);
+        let qty = |old: Qty| Qty::from_decimal(self.qty_value(old), lot_size, Rounding::Exact);
+
+        // Convert everything before touching the book
+        let mut nodes = self.arena.nodes.clone();
+        for node in nodes.iter_mut().flatten() {
+            node.price = price(node.price)?;
+            node.entry.quantity = qty(node.entry.quantity)?;
+            node.entry.remaining = qty(node.entry.remaining)?;
+            node.entry.display = node.entry.display.map(qty).transpose()?;
+        }
+        let rekey = |levels: &BTreeMap<Price, PriceLevel>| -> Result<BTreeMap<Price, PriceLevel>, String> {
+            levels
+                .values()
+                .map(|level| {
+                    let mut level = level.clone();
+                    level.price = price(level.price)?;
+                    level.lit_quantity = qty(level.lit_quantity)?;
+                    level.dark_quantity = qty(level.dark_quantity)?;
+                    Ok((level.price, level))
+                })
+                .collect()
+        };
+        let (bids, asks) = (rekey(&self.bids)?, rekey(&self.asks)?);
+
+        self.arena.nodes = nodes;
+        self.bids = bids;
+        self.asks = asks;
+        self.price_unit = price_unit;
+        self.lot_size = lot_size;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// The level at `price` on `side`, if anything rests there.
+    pub fn level(&self, side: OrderSide, price: Decimal) -> Option<&PriceLevel> {
+        let price = self.to_price(price, Rounding::Exact).ok()?;
+        match side {
+            OrderSide::Buy => self.bids.get(&price),
+            OrderSide::Sell => self.asks.get(&price),
+        }
+    }
+
+    /// Quantities of validated orders are whole lots; a sub-lot remainder
+    /// could never trade, so it is dropped.
+    fn lots(&self, quantity: Decimal) -> Result<Qty, String> {
+        self.to_qty(quantity, Rounding::Down)
+            .map_err(|e| format!("{} cannot hold quantity {}: {}", self.symbol, quantity, e))
+    }
+
+    fn resting_price(&self, side: OrderSide, price: Decimal) -> Result<Price, String> {
+        self.passive_price(side, price)
+            .map_err(|e| format!("{} cannot hold price {}: {}", self.symbol, price, e))
+    }
+
+    /// Rests `order` at the back of its price level. An order already in the
+    /// book is moved there. Fails, leaving the book as it was, if the order's
+    /// price or quantity does not fit the book's grid.
+    pub fn add_order(&mut self, order: &Order) -> Result<(), String> {
+        let remaining = self.lots(order.remaining_quantity())?;
+        let mut entry = LevelEntry {
+            order_id: order.id,
+            quantity: remaining,
+            remaining,
+            display: order.display_quantity.map(|display| self.lots(display)).transpose()?,
+            hidden: order.hidden,
+            pegged: order.peg.is_some(),
+        };
+        entry.quantity = entry.slice(remaining);
+
+        let node = Node {
+            entry,
+            side: order.side,
+            price: self.resting_price(order.side, order.price.unwrap_or(Decimal::ZERO))?,
+            prev: None,
+            next: None,
+        };
+
+        if self.index.contains_key(&order.id) {
+            self.remove_order(order);
+        }
+
+        let handle = self.arena.insert(node);
+        self.link(handle);
+        self.index.insert(order.id, handle);
+
+        if order.peg.is_some() {
+            self.pegged.push(order.id);
+        }
+        Ok(())
     }
 
     pub fn remove_order(&mut self, order: &Order) {
-        let price = order.price.unwrap_or(Decimal::ZERO);
-        let quantity = order.remaining_quantity();
+        if let Some(handle) = self.index.remove(&order.id) {
+            self.unlink(handle);
+            self.arena.remove(handle);
+        }
 
-        let book = match order.side {
-            OrderSide::Buy => &mut self.bids,
-            OrderSide::Sell => &mut self.asks,
+        if order.peg.is_some() {
+            self.pegged.retain(|&id| id != order.id);
+        }
+    }
+
+    /// Shrinks a resting order whose quantity was reduced, keeping its place
+    /// in the queue.
+    pub fn reduce_order(&mut self, order: &Order) -> Result<(), String> {
+        let Some(&handle) = self.index.get(&order.id) else {
+            return Ok(());
+        };
+        let remaining = self.lots(order.remaining_quantity())?;
+        let Some(node) = self.arena.get_mut(handle) else {
+            return Ok(());
+        };
+
+        let excess = node.entry.quantity - node.entry.quantity.min(remaining);
+        node.entry.quantity -= excess;
+        node.entry.remaining = remaining;
+        let (side, price, hidden) = (node.side, node.price, node.entry.hidden);
+
+        if let Some(level) = self.side_mut(side).get_mut(&price) {
+            *level.quantity_mut(hidden) -= excess;
+        }
+        Ok(())
+    }
+
+    /// Moves a resting order to the back of the queue at `new_price`.
+    pub fn move_order(&mut self, order: &Order, new_price: Decimal) -> Result<(), String> {
+        let Some(&handle) = self.index.get(&order.id) else {
+            return Ok(());
+        };
+        let new_price = self.resting_price(order.side, new_price)?;
+
+        self.unlink(handle);
+        if let Some(node) = self.arena.get_mut(handle) {
+            node.price = new_price;
+        }
+        self.link(handle);
+        Ok(())
+    }
+
+    /// Applies a fill to a resting order that has already been updated with
+    /// it. Once the displayed slice is used up the order either leaves the
+    /// book or, if it still has reserve quantity, is refreshed at the back
+    /// of its level. A fill larger than the slice, as an auction uncross
+    /// can make, takes the difference from the reserve.
+    pub fn fill_order(&mut self, order: &Order, quantity: Qty) {
+        let Some(&handle) = self.index.get(&order.id) else {
+            return;
+        };
+        let Some(node) = self.arena.get_mut(handle) else {
+            return;
         };
 
-        if let Some(level) = book.get_mut(&price) {
-            level.remove_order(order.id, quantity);
-            if level.orders.is_empty() {
-                book.remove(&price);
+        let shown = quantity.min(node.entry.quantity);
+        node.entry.quantity -= shown;
+        node.entry.remaining -= quantity.min(node.entry.remaining);
+        let (side, price, hidden, exhausted, done) = (
+            node.side,
+            node.price,
+            node.entry.hidden,
+            node.entry.quantity.is_zero(),
+            node.entry.remaining.is_zero(),
+        );
+
+        if let Some(level) = self.side_mut(side).get_mut(&price) {
+            *level.quantity_mut(hidden) -= shown;
+        }
+
+        if exhausted {
+            self.unlink(handle);
+            if done {
+                self.index.remove(&order.id);
+                self.arena.remove(handle);
+            } else {
+                if let Some(node) = self.arena.get_mut(handle) {
+                    node.entry.quantity = node.entry.slice(node.entry.remaining);
+                }
+                self.link(handle);
             }
         }
+
+        if done && order.peg.is_some() {
+            self.pegged.retain(|&id| id != order.id);
+        }
+    }
+
+    /// The entry of a resting order, looked up through the id index.
+    pub fn entry(&self, order_id: Uuid) -> Option<&LevelEntry> {
+        let handle = *self.index.get(&order_id)?;
+        self.arena.get(handle).map(|node| &node.entry)
+    }
+
+    /// The entries resting at `level` in priority order.
+    pub fn queue<'a>(&'a self, level: &PriceLevel) -> impl Iterator<Item = &'a LevelEntry> + 'a {
+        LevelIter {
+            arena: &self.arena,
+            next: level.lit.head,
+            dark: level.dark.head,
+        }
+    }
+
+    /// The order at the front of the queue on `side`, with its level price.
+    /// Hidden orders count, so this is where an incoming order would trade.
+    pub fn front(&self, side: OrderSide) -> Option<(Decimal, LevelEntry)> {
+        let level = match side {
+            OrderSide::Buy => self.bids.values().next_back(),
+            OrderSide::Sell => self.asks.values().next(),
+        }?;
+
+        self.queue(level).next().map(|entry| (self.price_value(level.price), *entry))
     }
 
+    /// Best displayed bid; levels holding only hidden orders are skipped.
     pub fn best_bid(&self) -> Option<Decimal> {
-        self.bids.keys().next_back().copied()
+        let level = self.bids.values().rev().find(|level| level.lit_quantity > Qty::ZERO)?;
+        Some(self.price_value(level.price))
     }
 
+    /// Best displayed ask; levels holding only hidden orders are skipped.
     pub fn best_ask(&self) -> Option<Decimal> {
-        self.asks.keys().next().copied()
+        let level = self.asks.values().find(|level| level.lit_quantity > Qty::ZERO)?;
+        Some(self.price_value(level.price))
+    }
+
+    /// Best displayed bid and ask ignoring pegged orders, used as the peg
+    /// reference so pegs never chase each other.
+    pub fn reference_prices(&self) -> (Option<Decimal>, Option<Decimal>) {
+        let unpegged = |level: &&PriceLevel| {
+            self.queue(level).any(|e| !e.hidden && !e.pegged)
+        };
+
+        let bid = self.bids.values().rev().find(unpegged).map(|l| self.price_value(l.price));
+        let ask = self.asks.values().find(unpegged).map(|l| self.price_value(l.price));
+        (bid, ask)
     }
 
     pub fn spread(&self) -> Option<Decimal> {
@@ -101,49 +494,199 @@ impl OrderBook {
         }
     }
 
-    pub fn depth(&self, side: OrderSide, levels: usize) -> Vec<(Decimal, Decimal)> {
-        let book = match side {
-            OrderSide::Buy => &self.bids,
-            OrderSide::Sell => &self.asks,
-        };
+    /// Checks the book's internal consistency: level totals and counts
+    /// match the entries queued there, the queues link up both ways, every
+    /// entry sits on its own side and price and is reachable through the
+    /// index, and no empty level is left behind.
+    pub fn verify_invariants(&self) -> Result<(), String> {
+        let mut queued = 0;
+
+        for (side, levels) in [(OrderSide::Buy, &self.bids), (OrderSide::Sell, &self.asks)] {
+            for (price, level) in levels {
+                let value = self.price_value(*price);
+                if level.price != *price {
+                    return Err(format!("Level keyed at {} has price {}", value, self.price_value(level.price)));
+                }
+                if level.is_empty() {
+                    return Err(format!("Empty {:?} level left at {}", side, value));
+                }
 
+                let mut count = 0;
+                for (hidden, queue) in [(false, level.lit), (true, level.dark)] {
+                    let mut quantity = Qty::ZERO;
+                    let mut prev = None;
+                    let mut cursor = queue.head;
+
+                    while let Some(handle) = cursor {
+                        let node = self.arena.get(handle).ok_or_else(|| format!("Dangling handle {}", handle))?;
+                        let entry = node.entry;
+
+                        if node.prev != prev {
+                            return Err(format!("Order {} is linked to the wrong predecessor", entry.order_id));
+                        }
+                        if node.side != side || node.price != *price || entry.hidden != hidden {
+                            return Err(format!("Order {} is queued in the wrong place", entry.order_id));
+                        }
+                        if entry.quantity <= Qty::ZERO || entry.quantity > entry.remaining {
+                            return Err(format!(
+                                "Order {} shows {} with {} remaining",
+                                entry.order_id,
+                                self.qty_value(entry.quantity),
+                                self.qty_value(entry.remaining)
+                            ));
+                        }
+                        if self.index.get(&entry.order_id) != Some(&handle) {
+                            return Err(format!("Order {} is missing from the index", entry.order_id));
+                        }
+
+                        quantity += entry.quantity;
+                        count += 1;
+                        prev = Some(handle);
+                        cursor = node.next;
+                    }
+
+                    if queue.tail != prev {
+                        return Err(format!("{:?} level {} has the wrong tail", side, value));
+                    }
+                    let total = if hidden { level.dark_quantity } else { level.lit_quantity };
+                    if total != quantity {
+                        return Err(format!(
+                            "{:?} level {} records {} {} but its orders hold {}",
+                            side,
+                            value,
+                            self.qty_value(total),
+                            if hidden { "dark" } else { "lit" },
+                            self.qty_value(quantity)
+                        ));
+                    }
+                }
+
+                if count != level.order_count {
+                    return Err(format!(
+                        "{:?} level {} counts {} orders but queues {}",
+                        side, value, level.order_count, count
+                    ));
+                }
+                queued += count;
+            }
+        }
+
+        let live = self.arena.nodes.iter().flatten().count();
+        if queued != self.index.len() || live != queued {
+            return Err(format!(
+                "{} orders queued, {} indexed and {} in the arena",
+                queued,
+                self.index.len(),
+                live
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn side_mut(&mut self, side: OrderSide) -> &mut BTreeMap<Price, PriceLevel> {
         match side {
-            OrderSide::Buy => book
-                .iter()
-                .rev()
-                .take(levels)
-                .map(|(price, level)| (*price, level.total_quantity))
-                .collect(),
-            OrderSide::Sell => book
-                .iter()
-                .take(levels)
-                .map(|(price, level)| (*price, level.total_quantity))
-                .collect(),
+            OrderSide::Buy => &mut self.bids,
+            OrderSide::Sell => &mut self.asks,
+        }
+    }
+
+    /// Appends a node to the back of its level's queue, creating the level
+    /// if needed.
+    fn link(&mut self, handle: OrderHandle) {
+        let Some(node) = self.arena.get(handle) else {
+            return;
+        };
+        let (side, price, entry) = (node.side, node.price, node.entry);
+
+        let levels = match side {
+            OrderSide::Buy => &mut self.bids,
+            OrderSide::Sell => &mut self.asks,
+        };
+        let level = levels.entry(price).or_insert_with(|| PriceLevel::new(price));
+        *level.quantity_mut(entry.hidden) += entry.quantity;
+        level.order_count += 1;
+
+        let queue = level.queue_mut(entry.hidden);
+        let tail = queue.tail.replace(handle);
+        if tail.is_none() {
+            queue.head = Some(handle);
+        }
+
+        if let Some(node) = self.arena.get_mut(handle) {
+            node.prev = tail;
+            node.next = None;
+        }
+        if let Some(tail) = tail.and_then(|tail| self.arena.get_mut(tail)) {
+            tail.next = Some(handle);
+        }
+    }
+
+    /// Takes a node out of its level's queue, dropping the level once it is
+    /// empty. The node itself stays in the arena.
+    fn unlink(&mut self, handle: OrderHandle) {
+        let Some(node) = self.arena.get(handle) else {
+            return;
+        };
+        let (side, price, entry, prev, next) = (node.side, node.price, node.entry, node.prev, node.next);
+
+        if let Some(prev) = prev.and_then(|prev| self.arena.get_mut(prev)) {
+            prev.next = next;
+        }
+        if let Some(next) = next.and_then(|next| self.arena.get_mut(next)) {
+            next.prev = prev;
+        }
+
+        let levels = self.side_mut(side);
+        let Some(level) = levels.get_mut(&price) else {
+            return;
+        };
+        *level.quantity_mut(entry.hidden) -= entry.quantity;
+        level.order_count -= 1;
+
+        let queue = level.queue_mut(entry.hidden);
+        if queue.head == Some(handle) {
+            queue.head = next;
+        }
+        if queue.tail == Some(handle) {
+            queue.tail = prev;
+        }
+
+        if level.is_empty() {
+            levels.remove(&price);
         }
     }
+
+    /// Displayed quantity per price, best first. Hidden orders never show.
+    pub fn depth(&self, side: OrderSide, levels: usize) -> Vec<(Decimal, Decimal)> {
+        let book: Box<dyn Iterator<Item = &PriceLevel>> = match side {
+            OrderSide::Buy => Box::new(self.bids.values().rev()),
+            OrderSide::Sell => Box::new(self.asks.values()),
+        };
+
+        book.filter(|level| level.lit_quantity > Qty::ZERO)
+            .take(levels)
+            .map(|level| (self.price_value(level.price), self.qty_value(level.lit_quantity)))
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::{OrderType, OrderStatus};
+    use crate::models::OrderType;
     use rust_decimal_macros::dec;
 
     fn create_test_order(side: OrderSide, price: Decimal, quantity: Decimal) -> Order {
-        Order {
-            id: Uuid::new_v4(),
-            symbol: "AAPL".to_string(),
+        Order::new(
+            "AAPL".to_string(),
             side,
-            order_type: OrderType::Limit,
+            OrderType::Limit,
             quantity,
-            filled_quantity: Decimal::ZERO,
-            price: Some(price),
-            stop_price: None,
-            status: OrderStatus::Pending,
-            user_id: "test_user".to_string(),
-            timestamp: chrono::Utc::now(),
-            updated_at: chrono::Utc::now(),
-        }
+            Some(price),
+            None,
+            "test_user".to_string(),
+        )
     }
 
     #[test]
@@ -161,8 +704,8 @@ mod tests {
         let buy_order = create_test_order(OrderSide::Buy, dec!(150.00), dec!(100));
         let sell_order = create_test_order(OrderSide::Sell, dec!(151.00), dec!(100));
 
-        book.add_order(&buy_order);
-        book.add_order(&sell_order);
+        book.add_order(&buy_order).unwrap();
+        book.add_order(&sell_order).unwrap();
 
         assert_eq!(book.best_bid(), Some(dec!(150.00)));
         assert_eq!(book.best_ask(), Some(dec!(151.00)));
@@ -175,8 +718,8 @@ mod tests {
         let buy_order = create_test_order(OrderSide::Buy, dec!(150.00), dec!(100));
         let sell_order = create_test_order(OrderSide::Sell, dec!(151.00), dec!(100));
 
-        book.add_order(&buy_order);
-        book.add_order(&sell_order);
+        book.add_order(&buy_order).unwrap();
+        book.add_order(&sell_order).unwrap();
 
         assert_eq!(book.spread(), Some(dec!(1.00)));
         assert_eq!(book.mid_price(), Some(dec!(150.50)));
@@ -186,10 +729,10 @@ mod tests {
     fn test_depth() {
         let mut book = OrderBook::new("AAPL".to_string());
         
-        book.add_order(&create_test_order(OrderSide::Buy, dec!(150.00), dec!(100)));
-        book.add_order(&create_test_order(OrderSide::Buy, dec!(149.00), dec!(200)));
-        book.add_order(&create_test_order(OrderSide::Sell, dec!(151.00), dec!(150)));
-        book.add_order(&create_test_order(OrderSide::Sell, dec!(152.00), dec!(250)));
+        book.add_order(&create_test_order(OrderSide::Buy, dec!(150.00), dec!(100))).unwrap();
+        book.add_order(&create_test_order(OrderSide::Buy, dec!(149.00), dec!(200))).unwrap();
+        book.add_order(&create_test_order(OrderSide::Sell, dec!(151.00), dec!(150))).unwrap();
+        book.add_order(&create_test_order(OrderSide::Sell, dec!(152.00), dec!(250))).unwrap();
 
         let bid_depth = book.depth(OrderSide::Buy, 2);
         assert_eq!(bid_depth.len(), 2);
@@ -201,4 +744,152 @@ mod tests {
         assert_eq!(ask_depth[0], (dec!(151.00), dec!(150)));
         assert_eq!(ask_depth[1], (dec!(152.00), dec!(250)));
     }
+
+    #[test]
+    fn test_iceberg_refresh() {
+        let mut book = OrderBook::new("AAPL".to_string());
+
+        let mut iceberg = create_test_order(OrderSide::Sell, dec!(151.00), dec!(500))
+            .with_display_quantity(dec!(100));
+        let lit = create_test_order(OrderSide::Sell, dec!(151.00), dec!(50));
+
+        book.add_order(&iceberg).unwrap();
+        book.add_order(&lit).unwrap();
+        assert_eq!(book.depth(OrderSide::Sell, 1), vec![(dec!(151.00), dec!(150))]);
+
+        iceberg.fill(dec!(60));
+        book.fill_order(&iceberg, Qty::new(60));
+        assert_eq!(book.depth(OrderSide::Sell, 1), vec![(dec!(151.00), dec!(90))]);
+        assert_eq!(book.front(OrderSide::Sell).unwrap().1.order_id, iceberg.id);
+
+        // Exhausting the slice refreshes it behind the lit order
+        iceberg.fill(dec!(40));
+        book.fill_order(&iceberg, Qty::new(40));
+        assert_eq!(book.depth(OrderSide::Sell, 1), vec![(dec!(151.00), dec!(150))]);
+        assert_eq!(book.front(OrderSide::Sell).unwrap().1.order_id, lit.id);
+        book.verify_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_hidden_orders_queue_behind_lit() {
+        let mut book = OrderBook::new("AAPL".to_string());
+
+        let hidden = create_test_order(OrderSide::Buy, dec!(150.00), dec!(300)).with_hidden();
+        let lit = create_test_order(OrderSide::Buy, dec!(150.00), dec!(100));
+        book.add_order(&hidden).unwrap();
+        book.add_order(&create_test_order(OrderSide::Buy, dec!(150.50), dec!(50)).with_hidden()).unwrap();
+        book.add_order(&lit).unwrap();
+
+        // Only displayed interest shows, and the hidden-only level is skipped
+        assert_eq!(book.best_bid(), Some(dec!(150.00)));
+        assert_eq!(book.depth(OrderSide::Buy, 5), vec![(dec!(150.00), dec!(100))]);
+        assert_eq!(book.front(OrderSide::Buy).unwrap().0, dec!(150.50));
+
+        let level = book.level(OrderSide::Buy, dec!(150.00)).unwrap();
+        let queue: Vec<Uuid> = book.queue(level).map(|e| e.order_id).collect();
+        assert_eq!(queue, vec![lit.id, hidden.id]);
+        assert_eq!((level.lit_quantity, level.dark_quantity), (Qty::new(100), Qty::new(300)));
+
+        book.remove_order(&lit);
+        assert_eq!(book.level(OrderSide::Buy, dec!(150.00)).unwrap().total_quantity(), Qty::new(300));
+        assert_eq!(book.best_bid(), None);
+        assert!(book.depth(OrderSide::Buy, 5).is_empty());
+        book.verify_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_cancel_unlinks_from_queue() {
+        let mut book = OrderBook::new("AAPL".to_string());
+        let orders: Vec<Order> = (0..3)
+            .map(|_| create_test_order(OrderSide::Buy, dec!(150.00), dec!(100)))
+            .collect();
+        for order in &orders {
+            book.add_order(order).unwrap();
+        }
+
+        book.remove_order(&orders[1]);
+        assert!(book.entry(orders[1].id).is_none());
+
+        // The freed node is reused by the next order, which still joins the back
+        let late = create_test_order(OrderSide::Buy, dec!(150.00), dec!(40));
+        book.add_order(&late).unwrap();
+        let level = book.level(OrderSide::Buy, dec!(150.00)).unwrap();
+        let queue: Vec<Uuid> = book.queue(level).map(|e| e.order_id).collect();
+        assert_eq!(queue, vec![orders[0].id, orders[2].id, late.id]);
+        assert_eq!((level.order_count, level.lit_quantity), (3, Qty::new(240)));
+        assert_eq!(book.arena.nodes.len(), 3);
+
+        book.move_order(&orders[0], dec!(149.00)).unwrap();
+        assert_eq!(book.front(OrderSide::Buy).unwrap().1.order_id, orders[2].id);
+        assert_eq!(book.depth(OrderSide::Buy, 2), vec![(dec!(150.00), dec!(140)), (dec!(149.00), dec!(100))]);
+        book.verify_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_unrepresentable_order_is_an_error() {
+        let mut book = OrderBook::new("AAPL".to_string());
+        let mut order = create_test_order(OrderSide::Buy, dec!(150.00), dec!(100));
+        book.add_order(&order).unwrap();
+
+        // Too far off the grid to fit an i64: the order stays where it was
+        let huge = dec!(100000000000000000000);
+        assert!(book.move_order(&order, huge).is_err());
+        assert_eq!(book.depth(OrderSide::Buy, 1), vec![(dec!(150.00), dec!(100))]);
+
+        order.price = Some(huge);
+        assert!(book.add_order(&order).is_err());
+        assert_eq!(book.depth(OrderSide::Buy, 1), vec![(dec!(150.00), dec!(100))]);
+
+        let other = create_test_order(OrderSide::Sell, dec!(151.00), huge);
+        assert!(book.add_order(&other).is_err());
+        assert!(book.entry(other.id).is_none());
+        book.verify_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_partial_fill_keeps_level_quantities() {
+        let mut book = OrderBook::new("AAPL".to_string());
+        let mut resting = create_test_order(OrderSide::Sell, dec!(151.00), dec!(100));
+        let other = create_test_order(OrderSide::Sell, dec!(151.00), dec!(50));
+        book.add_order(&resting).unwrap();
+        book.add_order(&other).unwrap();
+
+        resting.fill(dec!(30));
+        book.fill_order(&resting, Qty::new(30));
+        assert_eq!(book.entry(resting.id).unwrap().remaining, Qty::new(70));
+        assert_eq!(book.depth(OrderSide::Sell, 1), vec![(dec!(151.00), dec!(120))]);
+        book.verify_invariants().unwrap();
+
+        // Cancelling after the fill takes out exactly what was left
+        book.remove_order(&resting);
+        assert_eq!(book.depth(OrderSide::Sell, 1), vec![(dec!(151.00), dec!(50))]);
+        book.verify_invariants().unwrap();
+
+        book.asks.values_mut().next().unwrap().lit_quantity = Qty::new(80);
+        let error = book.verify_invariants().unwrap_err();
+        assert!(error.contains("records 80 lit but its orders hold 50"), "{}", error);
+    }
+
+    #[test]
+    fn test_rescale_keeps_resting_orders() {
+        let mut book = OrderBook::new("AAPL".to_string());
+        let iceberg = create_test_order(OrderSide::Sell, dec!(151.05), dec!(500)).with_display_quantity(dec!(100));
+        book.add_order(&iceberg).unwrap();
+        book.add_order(&create_test_order(OrderSide::Buy, dec!(150.00), dec!(300))).unwrap();
+
+        assert_eq!(book.to_price(dec!(151.05), Rounding::Exact), Ok(Price::new(30210)));
+        assert_eq!(book.passive_price(OrderSide::Buy, dec!(150.007)), Ok(Price::new(30001)));
+        assert_eq!(book.passive_price(OrderSide::Sell, dec!(150.007)), Ok(Price::new(30002)));
+
+        // Nothing changes when a resting order is off the new grid
+        assert!(book.rescale(dec!(0.10), dec!(100)).is_err());
+        assert_eq!((book.price_unit(), book.lot_size()), (dec!(0.005), dec!(1)));
+
+        book.rescale(dec!(0.05), dec!(100)).unwrap();
+        let entry = book.entry(iceberg.id).unwrap();
+        assert_eq!((entry.quantity, entry.remaining, entry.display), (Qty::new(1), Qty::new(5), Some(Qty::new(1))));
+        assert_eq!(book.depth(OrderSide::Sell, 1), vec![(dec!(151.05), dec!(100))]);
+        assert_eq!(book.depth(OrderSide::Buy, 1), vec![(dec!(150.00), dec!(300))]);
+        book.verify_invariants().unwrap();
+    }
 }
@@ -0,0 +1,105 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// What happens to an order that would trade outside the price band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BandAction {
+    /// Reject the order without executing any of it
+    Reject,
+    /// Execute up to the band, then halt the symbol into a re-opening auction
+    Halt,
+}
+
+/// Static and dynamic limits on execution prices for one symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceBands {
+    /// Maximum deviation from `reference_price`, in percent
+    pub static_percent: Option<Decimal>,
+    /// Maximum deviation from the last trade price, in percent
+    pub dynamic_percent: Option<Decimal>,
+    /// Static reference such as the previous close. Every auction uncross
+    /// replaces it with the uncrossing price.
+    pub reference_price: Option<Decimal>,
+    pub action: BandAction,
+}
+
+/// An execution price found outside the band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BandBreach {
+    pub price: Decimal,
+    pub lower: Option<Decimal>,
+    pub upper: Option<Decimal>,
+}
+
+impl PriceBands {
+    pub fn new(action: BandAction) -> Self {
+        Self {
+            static_percent: None,
+            dynamic_percent: None,
+            reference_price: None,
+            action,
+        }
+    }
+
+    pub fn with_static_band(mut self, percent: Decimal, reference_price: Decimal) -> Self {
+        self.static_percent = Some(percent);
+        self.reference_price = Some(reference_price);
+        self
+    }
+
+    pub fn with_dynamic_band(mut self, percent: Decimal) -> Self {
+        self.dynamic_percent = Some(percent);
+        self
+    }
+
+    /// The tighter of the static and dynamic limits on each side.
+    pub fn limits(&self, last_trade_price: Option<Decimal>) -> (Option<Decimal>, Option<Decimal>) {
+        let band = |percent: Option<Decimal>, reference: Option<Decimal>| {
+            let (percent, reference) = (percent?, reference?);
+            let width = reference * percent / Decimal::ONE_HUNDRED;
+            Some((reference - width, reference + width))
+        };
+
+        [
+            band(self.static_percent, self.reference_price),
+            band(self.dynamic_percent, last_trade_price),
+        ]
+        .into_iter()
+        .flatten()
+        .fold((None, None), |(lower, upper): (Option<Decimal>, Option<Decimal>), (low, high)| {
+            (
+                Some(lower.map_or(low, |lower| lower.max(low))),
+                Some(upper.map_or(high, |upper| upper.min(high))),
+            )
+        })
+    }
+
+    pub fn check(&self, price: Decimal, last_trade_price: Option<Decimal>) -> Option<BandBreach> {
+        let (lower, upper) = self.limits(last_trade_price);
+        let outside = lower.is_some_and(|lower| price < lower) || upper.is_some_and(|upper| price > upper);
+
+        outside.then_some(BandBreach { price, lower, upper })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_band_limits() {
+        let bands = PriceBands::new(BandAction::Reject)
+            .with_static_band(dec!(10), dec!(100))
+            .with_dynamic_band(dec!(5));
+
+        assert_eq!(bands.limits(None), (Some(dec!(90)), Some(dec!(110))));
+        // The dynamic band around 106 raises the floor, the static band still caps the top
+        assert_eq!(bands.limits(Some(dec!(106))), (Some(dec!(100.70)), Some(dec!(110))));
+
+        assert!(bands.check(dec!(109), Some(dec!(106))).is_none());
+        let breach = bands.check(dec!(100.50), Some(dec!(106))).unwrap();
+        assert_eq!(breach.lower, Some(dec!(100.70)));
+        assert!(PriceBands::new(BandAction::Halt).check(dec!(1000), Some(dec!(1))).is_none());
+    }
+}
@@ -0,0 +1,112 @@
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+
+/// Trading phase of a symbol. Symbols without an explicit session trade
+/// continuously.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+pub enum SessionState {
+    /// Opening auction call: orders rest without matching
+    PreOpen,
+    #[default]
+    Continuous,
+    /// Closing auction call: orders rest without matching
+    PreClose,
+    /// Only cancels are accepted
+    Halted,
+    /// Nothing is accepted
+    Closed,
+}
+
+impl SessionState {
+    pub fn accepts_orders(&self) -> bool {
+        matches!(self, SessionState::PreOpen | SessionState::Continuous | SessionState::PreClose)
+    }
+
+    pub fn accepts_cancels(&self) -> bool {
+        *self != SessionState::Closed
+    }
+
+    /// Whether the call auction for this phase is running.
+    pub fn is_auction_call(&self) -> bool {
+        matches!(self, SessionState::PreOpen | SessionState::PreClose)
+    }
+
+    pub fn can_transition_to(&self, next: SessionState) -> bool {
+        use SessionState::*;
+
+        match (self, next) {
+            (from, to) if *from == to => false,
+            (Closed, PreOpen) => true,
+            (Closed, _) => false,
+            (_, Halted) | (_, Closed) => true,
+            (PreOpen, Continuous) => true,
+            (Continuous, PreClose) => true,
+            (Halted, PreOpen) | (Halted, Continuous) | (Halted, PreClose) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Daily schedule of session changes. Each entry takes effect at its time
+/// and lasts until the next one; before the first entry of the day the last
+/// entry of the previous day applies.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timetable {
+    pub entries: Vec<(NaiveTime, SessionState)>,
+}
+
+impl Timetable {
+    pub fn new(mut entries: Vec<(NaiveTime, SessionState)>) -> Self {
+        entries.sort_by_key(|(time, _)| *time);
+        Self { entries }
+    }
+
+    pub fn state_at(&self, time: NaiveTime) -> Option<SessionState> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= time)
+            .or_else(|| self.entries.last())
+            .map(|(_, state)| *state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_session_transitions() {
+        use SessionState::*;
+
+        assert!(Closed.can_transition_to(PreOpen));
+        assert!(!Closed.can_transition_to(Continuous));
+        assert!(PreOpen.can_transition_to(Continuous));
+        assert!(Continuous.can_transition_to(Halted));
+        assert!(Halted.can_transition_to(PreOpen));
+        assert!(!Continuous.can_transition_to(Continuous));
+        assert!(!Continuous.can_transition_to(PreOpen));
+
+        assert!(PreOpen.accepts_orders() && !Halted.accepts_orders());
+        assert!(Halted.accepts_cancels() && !Closed.accepts_cancels());
+    }
+
+    #[test]
+    fn test_timetable_state_at() {
+        let timetable = Timetable::new(vec![
+            (at(9, 30), SessionState::Continuous),
+            (at(16, 0), SessionState::Closed),
+            (at(9, 0), SessionState::PreOpen),
+        ]);
+
+        assert_eq!(timetable.state_at(at(8, 0)), Some(SessionState::Closed));
+        assert_eq!(timetable.state_at(at(9, 0)), Some(SessionState::PreOpen));
+        assert_eq!(timetable.state_at(at(12, 0)), Some(SessionState::Continuous));
+        assert_eq!(timetable.state_at(at(17, 0)), Some(SessionState::Closed));
+        assert_eq!(Timetable::new(Vec::new()).state_at(at(12, 0)), None);
+    }
+}
@@ -0,0 +1,181 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use super::{Order, OrderSide};
+
+/// Holds untriggered stop orders for one symbol, keyed by stop price.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StopBook {
+    pub symbol: String,
+    pub buy_stops: BTreeMap<Decimal, Vec<Uuid>>,
+    pub sell_stops: BTreeMap<Decimal, Vec<Uuid>>,
+    /// Parked orders whose stop price trails the market, in arrival order
+    pub trailing: Vec<Uuid>,
+}
+
+impl StopBook {
+    pub fn new(symbol: String) -> Self {
+        Self {
+            symbol,
+            buy_stops: BTreeMap::new(),
+            sell_stops: BTreeMap::new(),
+            trailing: Vec::new(),
+        }
+    }
+
+    pub fn add_order(&mut self, order: &Order) {
+        let stop_price = order.stop_price.unwrap_or(Decimal::ZERO);
+
+        let book = match order.side {
+            OrderSide::Buy => &mut self.buy_stops,
+            OrderSide::Sell => &mut self.sell_stops,
+        };
+
+        book.entry(stop_price).or_default().push(order.id);
+
+        if order.trailing_stop.is_some() {
+            self.trailing.push(order.id);
+        }
+    }
+
+    pub fn contains(&self, order: &Order) -> bool {
+        let stop_price = order.stop_price.unwrap_or(Decimal::ZERO);
+
+        let book = match order.side {
+            OrderSide::Buy => &self.buy_stops,
+            OrderSide::Sell => &self.sell_stops,
+        };
+
+        book.get(&stop_price).is_some_and(|ids| ids.contains(&order.id))
+    }
+
+    pub fn remove_order(&mut self, order: &Order) -> bool {
+        let stop_price = order.stop_price.unwrap_or(Decimal::ZERO);
+
+        let book = match order.side {
+            OrderSide::Buy => &mut self.buy_stops,
+            OrderSide::Sell => &mut self.sell_stops,
+        };
+
+        let Some(ids) = book.get_mut(&stop_price) else {
+            return false;
+        };
+
+        let before = ids.len();
+        ids.retain(|&id| id != order.id);
+        let removed = ids.len() < before;
+
+        if ids.is_empty() {
+            book.remove(&stop_price);
+        }
+
+        if removed {
+            self.trailing.retain(|&id| id != order.id);
+        }
+
+        removed
+    }
+
+    /// Removes and returns every stop triggered by `last_price`.
+    ///
+    /// Buy stops fire when the last price trades at or above their stop and
+    /// come out lowest stop first; sell stops fire at or below their stop and
+    /// come out highest stop first. Orders sharing a stop price keep arrival
+    /// order, and buy stops are always released before sell stops.
+    pub fn take_triggered(&mut self, last_price: Decimal) -> Vec<Uuid> {
+        let mut triggered = Vec::new();
+
+        let buy_prices: Vec<Decimal> = self
+            .buy_stops
+            .range(..=last_price)
+            .map(|(price, _)| *price)
+            .collect();
+        for price in buy_prices {
+            if let Some(ids) = self.buy_stops.remove(&price) {
+                triggered.extend(ids);
+            }
+        }
+
+        let sell_prices: Vec<Decimal> = self
+            .sell_stops
+            .range(last_price..)
+            .rev()
+            .map(|(price, _)| *price)
+            .collect();
+        for price in sell_prices {
+            if let Some(ids) = self.sell_stops.remove(&price) {
+                triggered.extend(ids);
+            }
+        }
+
+        if !self.trailing.is_empty() {
+            self.trailing.retain(|id| !triggered.contains(id));
+        }
+
+        triggered
+    }
+
+    pub fn len(&self) -> usize {
+        self.buy_stops.values().map(Vec::len).sum::<usize>()
+            + self.sell_stops.values().map(Vec::len).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buy_stops.is_empty() && self.sell_stops.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::OrderType;
+    use rust_decimal_macros::dec;
+
+    fn create_stop_order(side: OrderSide, stop_price: Decimal) -> Order {
+        Order::new(
+            "AAPL".to_string(),
+            side,
+            OrderType::StopLoss,
+            dec!(100),
+            None,
+            Some(stop_price),
+            "test_user".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_take_triggered_order() {
+        let mut book = StopBook::new("AAPL".to_string());
+
+        let buy_far = create_stop_order(OrderSide::Buy, dec!(152.00));
+        let buy_near = create_stop_order(OrderSide::Buy, dec!(151.00));
+        let buy_near_later = create_stop_order(OrderSide::Buy, dec!(151.00));
+        let buy_untouched = create_stop_order(OrderSide::Buy, dec!(153.00));
+        let sell_stop = create_stop_order(OrderSide::Sell, dec!(148.00));
+
+        for order in [&buy_far, &buy_near, &buy_near_later, &buy_untouched, &sell_stop] {
+            book.add_order(order);
+        }
+
+        let triggered = book.take_triggered(dec!(152.00));
+        assert_eq!(triggered, vec![buy_near.id, buy_near_later.id, buy_far.id]);
+        assert_eq!(book.len(), 2);
+
+        let triggered = book.take_triggered(dec!(147.50));
+        assert_eq!(triggered, vec![sell_stop.id]);
+        assert_eq!(book.len(), 1);
+    }
+
+    #[test]
+    fn test_remove_stop_order() {
+        let mut book = StopBook::new("AAPL".to_string());
+        let order = create_stop_order(OrderSide::Sell, dec!(148.00));
+
+        book.add_order(&order);
+        assert!(book.remove_order(&order));
+        assert!(!book.remove_order(&order));
+        assert!(book.is_empty());
+    }
+}
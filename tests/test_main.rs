@@ -3,12 +3,13 @@
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use rust_hft_trading_engine::MatchingEngine;
 
     #[test]
     fn test_project_compiles() {
         // If this test runs, the project compiled successfully
-        assert!(true, "Project should compile");
+        let engine = MatchingEngine::new();
+        assert!(engine.get_orderbook("AAPL").is_none(), "Project should compile");
     }
 
     #[test]